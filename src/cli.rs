@@ -0,0 +1,1289 @@
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use clap_complete::env::{CompleteEnv, Shells};
+use dialoguer::{Confirm, Select};
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::mirror::Mirror;
+use crate::{completions, config, doctor, forge, fs, git, spec, tui, util};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Git repository URL
+    #[arg(required = true, add = ArgValueCompleter::new(complete_repo))]
+    repo: Option<String>,
+
+    /// Root directory where projects are stored
+    #[arg(short, long, global = true, default_value = "~/Projects")]
+    root: String,
+
+    /// Print a shell-friendly cd command instead of executing
+    #[arg(long)]
+    print_cd: bool,
+
+    /// Dry run: show commands without executing
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Open the repo in VS Code after cloning or when it exists
+    #[arg(long)]
+    open_vs_code: bool,
+
+    /// Do not open the repo in VS Code
+    #[arg(long)]
+    no_open_vs_code: bool,
+
+    /// Disable interactive prompts (useful in CI)
+    #[arg(long, global = true)]
+    no_prompt: bool,
+
+    /// Use full host domain in local path (e.g. `github.com` instead of `github`)
+    #[arg(long, global = true)]
+    full_host: bool,
+
+    /// Include a non-default port in the host directory name (e.g. `git.corp_2222`)
+    #[arg(long, global = true)]
+    include_port: bool,
+
+    /// Lowercase the owner/organisation directory so differently-cased URLs share one path
+    #[arg(long, global = true)]
+    lowercase_owner: bool,
+
+    /// How to update an existing repository after fetching
+    #[arg(long, value_enum, default_value_t = Update::Fetch)]
+    update: Update,
+
+    /// Check out a pull/merge request in a separate worktree next to the repository
+    #[arg(long)]
+    worktree: bool,
+
+    /// Fast-forward every local branch whose upstream is strictly ahead
+    #[arg(long)]
+    ff_branches: bool,
+
+    /// Print a machine-readable JSON report on stdout (progress goes to stderr)
+    #[arg(long)]
+    json: bool,
+
+    /// What to do when an existing repository's origin doesn't match the requested URL
+    /// (prompts when omitted; aborts without a prompt)
+    #[arg(long, value_enum)]
+    on_mismatch: Option<OnMismatch>,
+
+    /// Move a non-empty, non-repository directory at the target path aside to a
+    /// timestamped backup before cloning
+    #[arg(long)]
+    force: bool,
+}
+
+/// Choices offered when the repository found at the target path has a
+/// different origin than the requested URL.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OnMismatch {
+    /// Stop without touching the repository
+    Abort,
+    /// Keep origin and add the requested URL as an extra remote
+    AddRemote,
+    /// Re-point origin at the requested URL
+    SetOrigin,
+}
+
+/// Values of `--update`, mapped onto [`git::UpdateMode`].
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Update {
+    /// Only fetch; never touch local branches
+    Fetch,
+    /// Fast-forward the checked-out branch to its upstream
+    Pull,
+    /// Rebase the checked-out branch onto its upstream
+    Rebase,
+}
+
+impl From<Update> for git::UpdateMode {
+    fn from(update: Update) -> git::UpdateMode {
+        match update {
+            Update::Fetch => git::UpdateMode::Fetch,
+            Update::Pull => git::UpdateMode::Pull,
+            Update::Rebase => git::UpdateMode::Rebase,
+        }
+    }
+}
+
+/// Machine-readable summary of a clone or fetch run, printed with `--json`.
+#[derive(Serialize)]
+struct RunReport {
+    repo: String,
+    path: PathBuf,
+    /// "cloned" or "fetched"
+    action: &'static str,
+    /// Layout of the repository found on disk, when it already existed
+    kind: Option<git::RepoKind>,
+    dry_run: bool,
+    fetch: Option<git::FetchSummary>,
+    update: Option<UpdateReport>,
+    branches: Option<git::BranchSyncReport>,
+}
+
+#[derive(Serialize)]
+struct UpdateReport {
+    mode: Update,
+    updated: bool,
+    message: String,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Delete local branches whose upstream is gone or that are merged into the default branch
+    PruneBranches {
+        /// Repository URL or local path (default: every repository under the root)
+        #[arg(add = ArgValueCompleter::new(complete_repo))]
+        target: Option<String>,
+
+        /// Delete without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Open the repository's web page, or a file, branch or commit in it
+    Browse {
+        /// Repository directory or a file in it (default: current directory)
+        path: Option<PathBuf>,
+
+        /// Link to the checked-out branch
+        #[arg(long, conflicts_with = "commit")]
+        branch: bool,
+
+        /// Link to the commit at HEAD (pins file links to it)
+        #[arg(long)]
+        commit: bool,
+
+        /// Highlight this line of the file
+        #[arg(short, long)]
+        line: Option<u32>,
+
+        /// Print the URL instead of opening it
+        #[arg(long)]
+        print: bool,
+    },
+    /// Check git, editors, the root, config, ssh and credentials for problems
+    Doctor,
+    /// Browse, fetch, pull, open and remove mirrored repositories in a terminal dashboard
+    Tui,
+    /// Print the script that enables tab completion in a shell
+    Completions {
+        #[arg(value_parser = ["bash", "elvish", "fish", "powershell", "zsh"])]
+        shell: String,
+    },
+    /// Move repositories whose origin URL no longer matches their directory
+    Relocate {
+        /// Repository path (default: every repository under the root)
+        #[arg(add = ArgValueCompleter::new(complete_repo))]
+        target: Option<String>,
+
+        /// Move without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Delete a mirrored repository and the directories it leaves empty
+    Remove {
+        /// Repository URL or local path
+        #[arg(add = ArgValueCompleter::new(complete_repo))]
+        target: String,
+
+        /// Remove even with uncommitted changes, stashes or unpushed commits
+        #[arg(long)]
+        force: bool,
+
+        /// Remove without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// List an owner's repositories on a forge and which of them are mirrored
+    List {
+        /// User, group or organisation (`org[/project]` on Azure DevOps)
+        owner: String,
+
+        /// Forge host to ask (default: the configured default host)
+        #[arg(long)]
+        host: Option<String>,
+    },
+}
+
+/// Parse the command line and run the requested command.
+pub fn run() {
+    // Answers tab-completion requests from the registered shell script
+    CompleteEnv::with_factory(Cli::command).complete();
+    let cli = Cli::parse();
+
+    match &cli.command {
+        Some(Command::PruneBranches { target, yes }) => {
+            prune_branches(&cli, target.as_deref(), *yes)
+        }
+        Some(Command::Completions { shell }) => print_completions(shell),
+        Some(Command::Tui) => dashboard(&cli),
+        Some(Command::Doctor) => doctor(&cli),
+        Some(Command::Relocate { target, yes }) => relocate(&cli, target.as_deref(), *yes),
+        Some(Command::Remove { target, force, yes }) => remove(&cli, target, *force, *yes),
+        Some(Command::List { owner, host }) => list(&cli, owner, host.as_deref()),
+        Some(Command::Browse {
+            path,
+            branch,
+            commit,
+            line,
+            print,
+        }) => {
+            let path = path.as_deref().unwrap_or(Path::new("."));
+            if let Err(e) = browse(&cli, path, *branch, *commit, *line, *print) {
+                eprintln!("{}", format!("Error: {:#}", e).red());
+                process::exit(1);
+            }
+        }
+        None => mirror_repo(&cli),
+    }
+}
+
+/// Complete a repository argument against the root and layout given on the
+/// command line being completed, which the completion engine passes after `--`.
+fn complete_repo(current: &OsStr) -> Vec<CompletionCandidate> {
+    let args = env::args_os().skip_while(|a| a != "--").skip(1);
+    let Some(cli) = Cli::command()
+        .ignore_errors(true)
+        .try_get_matches_from(args)
+        .ok()
+        .and_then(|matches| Cli::from_arg_matches(&matches).ok())
+    else {
+        return Vec::new();
+    };
+    let config = config::load().unwrap_or_default();
+    completions::complete_repo(current, &cli.root, &config, &layout(&cli, &config))
+}
+
+fn print_completions(shell: &str) {
+    let name = Cli::command().get_name().to_string();
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell)
+        .expect("clap only accepts supported shells");
+    match completer.write_registration("COMPLETE", &name, &name, &name, &mut io::stdout()) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("{}", format!("Error writing completions: {}", e).red());
+            process::exit(1);
+        }
+    }
+}
+
+fn dashboard(cli: &Cli) {
+    let root = match fs::expand_root(&cli.root) {
+        Ok(root) => root,
+        Err(e) => {
+            eprintln!("{}", format!("Error resolving root: {}", e).red());
+            process::exit(1);
+        }
+    };
+    if let Err(e) = tui::run(&root, layout(cli, &load_config()), cli.dry_run) {
+        eprintln!("{}", format!("Error: {:#}", e).red());
+        process::exit(1);
+    }
+}
+
+/// Clone the repository named on the command line, or bring its existing
+/// mirror up to date.
+fn mirror_repo(cli: &Cli) {
+    let repo = cli
+        .repo
+        .clone()
+        .expect("clap requires a repository URL when no subcommand is given");
+
+    let config = load_config();
+    let mirror = Mirror::new(cli.root.clone(), layout(cli, &config)).dry_run(cli.dry_run);
+    // `owner/repo` and `owner/repo#123` refer to the default host
+    let repo = spec::expand_shorthand(&repo, &config.default_host).unwrap_or(repo);
+    // A pasted browser URL names a repository plus a branch, file or commit in it
+    let (repo, web_link) = match spec::RepoSpec::parse(&repo)
+        .ok()
+        .and_then(|spec| mirror.layout().forges.web_link(&spec))
+    {
+        Some((link, clone_url)) => (clone_url, Some(link)),
+        None => (repo, None),
+    };
+
+    let local = match mirror.local_path(&repo) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", format!("Error building local path: {}", e).red());
+            process::exit(1);
+        }
+    };
+
+    // If the user only wants the cd command, print it and exit early
+    if cli.print_cd {
+        println!("{}", format!("cd \"{}\"", local.display()).green());
+        return;
+    }
+
+    // With --json, human-readable progress goes to stderr so stdout carries
+    // only the JSON report
+    let mut out: Box<dyn Write> = if cli.json {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    };
+    let mut report = RunReport {
+        repo: repo.clone(),
+        path: local.clone(),
+        action: "cloned",
+        kind: None,
+        dry_run: cli.dry_run,
+        fetch: None,
+        update: None,
+        branches: None,
+    };
+
+    // Decide whether to open VS Code: explicit flags take precedence,
+    // otherwise prompt the user. If running in CI or `--no-prompt` is set, do not prompt.
+    let is_ci = env::var("CI").is_ok();
+    let decide_open = |default: bool| -> bool {
+        // Always open if explicitly requested
+        if cli.open_vs_code {
+            return true;
+        }
+
+        // Never open if explicitly disabled
+        if cli.no_open_vs_code {
+            return false;
+        }
+
+        // Never open in CI environments
+        if is_ci {
+            return false;
+        }
+
+        // Use default without prompting if --no-prompt is set
+        if cli.no_prompt {
+            return default;
+        }
+
+        // Otherwise, prompt the user
+        Confirm::new()
+            .with_prompt("Open the repository in VS Code?")
+            .default(default)
+            .interact()
+            .unwrap_or(default)
+    };
+
+    // If the repo already exists locally, fetch updates
+    if let Some(kind) = git::detect_repo(&local) {
+        report.action = "fetched";
+        report.kind = Some(kind);
+        say(
+            &mut out,
+            format!(
+                "Found existing repository ({}) at {}",
+                kind,
+                local.display()
+            ),
+        );
+        if !verify_origin(cli, &repo, &local, is_ci, &mut out) {
+            process::exit(1);
+        }
+        if cli.dry_run {
+            say(
+                &mut out,
+                format!("> Dry run: Fetching repository: {}", local.display()).yellow(),
+            );
+        } else {
+            match mirror.fetch_with_writer(&repo, &mut out) {
+                Ok(summary) => {
+                    say(
+                        &mut out,
+                        format!("Fetched repository at {}", local.display()).green(),
+                    );
+                    say(&mut out, summary.to_string().cyan());
+                    report.fetch = Some(summary);
+                }
+                Err(e) => {
+                    eprintln!("{}", format!("Error fetching repo: {}", e).red());
+                    process::exit(1);
+                }
+            }
+        }
+
+        if kind == git::RepoKind::Bare {
+            if cli.update != Update::Fetch || cli.ff_branches {
+                say(
+                    &mut out,
+                    "Bare repository has no working tree; local branches left untouched".yellow(),
+                );
+            }
+        } else {
+            // A branch that can't be moved is reported, not treated as a failure
+            match git::update_current_branch_with_writer(
+                &local,
+                cli.update.into(),
+                cli.dry_run,
+                &mut out,
+            ) {
+                Ok(outcome) => {
+                    if outcome.updated() {
+                        say(&mut out, outcome.to_string().green());
+                    } else if cli.update != Update::Fetch && !cli.dry_run {
+                        say(&mut out, outcome.to_string().yellow());
+                    }
+                    if cli.update != Update::Fetch && !cli.dry_run {
+                        report.update = Some(UpdateReport {
+                            mode: cli.update,
+                            updated: outcome.updated(),
+                            message: outcome.to_string(),
+                        });
+                    }
+                }
+                Err(e) => eprintln!(
+                    "{}",
+                    format!("Warning: failed to update branch: {}", e).yellow()
+                ),
+            }
+
+            if cli.ff_branches {
+                match git::fast_forward_branches_with_writer(&local, cli.dry_run, &mut out) {
+                    Ok(branches) if !cli.dry_run => {
+                        say(&mut out, branches.to_string().cyan());
+                        report.branches = Some(branches);
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!(
+                        "{}",
+                        format!("Warning: failed to fast-forward branches: {}", e).yellow()
+                    ),
+                }
+            }
+        }
+
+        let landing = match &web_link {
+            Some(link) if kind != git::RepoKind::Bare => {
+                follow_web_link(cli, &local, link, &mut out)
+            }
+            _ => Landing::at(&local),
+        };
+        if decide_open(true) {
+            open_editor(cli, &landing, &mut out);
+        }
+
+        say(
+            &mut out,
+            format!(
+                "To move to the project's directory, please run: \"cd {}\"",
+                landing.dir.display()
+            )
+            .cyan(),
+        );
+        if cli.json {
+            print_json(&report);
+        }
+        return;
+    }
+
+    // Repo doesn't exist: make sure nothing else is in the way, then clone it
+    if !prepare_clone_target(cli, &local, &mut out) {
+        process::exit(1);
+    }
+    if let Err(e) = mirror.clone_repo_with_writer(&repo, &mut out) {
+        eprintln!("{}", format!("Error cloning repo: {}", e).red());
+        process::exit(1);
+    } else {
+        say(
+            &mut out,
+            format!("Repository cloned to {}", local.display()).green(),
+        );
+    }
+
+    let landing = match &web_link {
+        Some(link) => follow_web_link(cli, &local, link, &mut out),
+        None => Landing::at(&local),
+    };
+    if decide_open(true) {
+        open_editor(cli, &landing, &mut out);
+    }
+
+    say(
+        &mut out,
+        format!(
+            "To move to the project's directory, please run: \"cd {}\"",
+            landing.dir.display()
+        )
+        .cyan(),
+    );
+
+    if cli.json {
+        print_json(&report);
+        return;
+    }
+
+    // Emit the path for use in shell aliases (e.g., mirror = "!git-mirror && cd $_")
+    println!("{}", landing.dir.display());
+}
+
+/// Make sure the repository found at `local` is the one that was requested
+/// before reusing it. Returns false if the run should stop.
+fn verify_origin(cli: &Cli, repo: &str, local: &Path, is_ci: bool, out: &mut dyn Write) -> bool {
+    let remotes = match git::remotes(local) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", format!("Error reading remotes: {}", e).red());
+            return false;
+        }
+    };
+    // A remote added by an earlier `--on-mismatch add-remote` run counts too
+    if remotes
+        .iter()
+        .any(|(_, url)| util::same_repo_url(url, repo))
+    {
+        return true;
+    }
+
+    let found = remotes
+        .iter()
+        .find(|(name, _)| name == "origin")
+        .map(|(_, url)| util::sanitize_url(url))
+        .unwrap_or_else(|| "no origin remote".to_string());
+    eprintln!(
+        "{}",
+        format!(
+            "The repository at {} has origin {}, which does not match {}",
+            local.display(),
+            found,
+            util::sanitize_url(repo)
+        )
+        .yellow()
+    );
+
+    let choice = match cli.on_mismatch {
+        Some(choice) => choice,
+        None if cli.no_prompt || is_ci => OnMismatch::Abort,
+        None => {
+            let choices = [
+                (OnMismatch::Abort, "Abort"),
+                (
+                    OnMismatch::AddRemote,
+                    "Add the requested URL as an extra remote",
+                ),
+                (
+                    OnMismatch::SetOrigin,
+                    "Re-point origin to the requested URL",
+                ),
+            ];
+            let labels: Vec<&str> = choices.iter().map(|(_, l)| *l).collect();
+            let picked = Select::new()
+                .with_prompt("How should git-mirror proceed?")
+                .items(&labels)
+                .default(0)
+                .interact()
+                .unwrap_or(0);
+            choices[picked].0
+        }
+    };
+
+    let result = match choice {
+        OnMismatch::Abort => {
+            eprintln!(
+                "{}",
+                "Aborting; pass --on-mismatch to choose another action".red()
+            );
+            return false;
+        }
+        OnMismatch::AddRemote => {
+            let name = util::remote_name_for(repo);
+            if remotes.iter().any(|(n, _)| *n == name) {
+                Ok(format!(
+                    "Remote {} already exists; leaving it unchanged",
+                    name
+                ))
+            } else {
+                git::add_remote_with_writer(local, &name, repo, cli.dry_run, out)
+                    .map(|_| format!("Added remote {} -> {}", name, util::sanitize_url(repo)))
+            }
+        }
+        OnMismatch::SetOrigin => git::set_origin_with_writer(local, repo, cli.dry_run, out)
+            .map(|_| format!("Origin now points to {}", util::sanitize_url(repo))),
+    };
+    match result {
+        Ok(msg) => {
+            if !cli.dry_run {
+                eprintln!("{}", msg.green());
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("{}", format!("Error updating remotes: {}", e).red());
+            false
+        }
+    }
+}
+
+/// Handle whatever already occupies the clone target: an empty directory is
+/// cloned into, anything else is refused unless `--force` moves it aside.
+/// Returns false if the run should stop.
+fn prepare_clone_target(cli: &Cli, local: &Path, out: &mut dyn Write) -> bool {
+    let state = match fs::inspect_target(local) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}", format!("Error inspecting target: {}", e).red());
+            return false;
+        }
+    };
+    let what = match state {
+        fs::TargetState::Missing | fs::TargetState::EmptyDir => return true,
+        fs::TargetState::NonEmptyDir(n) => format!("a directory with {} entries", n),
+        fs::TargetState::NotADirectory => "a file".to_string(),
+    };
+
+    if !cli.force {
+        eprintln!(
+            "{}",
+            format!(
+                "Cannot clone into {}: it is {} but not a git repository \
+                 (perhaps a failed clone or leftover files). Move it away, or pass \
+                 --force to move it aside to a timestamped backup.",
+                local.display(),
+                what
+            )
+            .red()
+        );
+        return false;
+    }
+
+    if cli.dry_run {
+        say(
+            out,
+            format!(
+                "Dry run: move {} aside to a timestamped backup",
+                local.display()
+            ),
+        );
+        return true;
+    }
+    match fs::backup_existing(local) {
+        Ok(backup) => {
+            say(
+                out,
+                format!("Moved existing contents to {}", backup.display()).yellow(),
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!("{}", format!("Error: {:#}", e).red());
+            false
+        }
+    }
+}
+
+/// Where a run ends up: the directory to work in and, for links to a file,
+/// the file and line to open.
+struct Landing {
+    dir: PathBuf,
+    goto: Option<(PathBuf, Option<u32>)>,
+}
+
+impl Landing {
+    fn at(dir: &Path) -> Landing {
+        Landing {
+            dir: dir.to_path_buf(),
+            goto: None,
+        }
+    }
+}
+
+/// Check out what a browser URL points at: a branch or tag (optionally a file
+/// and line in it), a commit, or a pull/merge request. Failures are warnings;
+/// the repository itself is already in place.
+fn follow_web_link(cli: &Cli, local: &Path, link: &forge::WebLink, out: &mut dyn Write) -> Landing {
+    let landing = Landing::at(local);
+    if cli.dry_run && !local.exists() {
+        say(out, "Dry run: check out the linked revision after cloning");
+        return landing;
+    }
+    let (target, path, line) = match &link.target {
+        forge::WebLinkTarget::Ref { rest, line } => match git::resolve_web_ref(local, rest) {
+            Ok((target, path)) => (target, path, *line),
+            Err(e) => {
+                eprintln!("{}", format!("Warning: {}", e).yellow());
+                return landing;
+            }
+        },
+        forge::WebLinkTarget::Commit(sha) => {
+            (git::CheckoutTarget::Detached(sha.clone()), Vec::new(), None)
+        }
+        forge::WebLinkTarget::PullRequest {
+            number,
+            git_ref,
+            branch,
+        } => return checkout_pull_request(cli, local, *number, git_ref.as_deref(), branch, out),
+    };
+    if let Err(e) = git::checkout_with_writer(local, &target, cli.dry_run, out) {
+        eprintln!(
+            "{}",
+            format!("Warning: failed to check out {}: {:#}", target, e).yellow()
+        );
+        return landing;
+    }
+
+    let file: PathBuf = path.iter().collect();
+    Landing {
+        goto: (!path.is_empty() && local.join(&file).is_file()).then_some((file, line)),
+        ..landing
+    }
+}
+
+/// Fetch a pull/merge request into `branch` and check it out, in a sibling
+/// worktree (`<repo>.<branch>`) with `--worktree`.
+fn checkout_pull_request(
+    cli: &Cli,
+    local: &Path,
+    number: u64,
+    git_ref: Option<&str>,
+    branch: &str,
+    out: &mut dyn Write,
+) -> Landing {
+    let Some(git_ref) = git_ref else {
+        eprintln!(
+            "{}",
+            format!(
+                "Warning: this forge does not publish a ref for pull request #{}; check it out manually",
+                number
+            )
+            .yellow()
+        );
+        return Landing::at(local);
+    };
+    let worktree = cli.worktree.then(|| {
+        let name = local.file_name().unwrap_or_default().to_string_lossy();
+        local.with_file_name(format!("{}.{}", name, branch.replace('/', "-")))
+    });
+    match git::checkout_pull_request_with_writer(
+        local,
+        git_ref,
+        branch,
+        worktree.as_deref(),
+        cli.dry_run,
+        out,
+    ) {
+        Ok(dir) => Landing::at(&dir),
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Warning: failed to check out pull request #{}: {:#}",
+                    number, e
+                )
+                .yellow()
+            );
+            Landing::at(local)
+        }
+    }
+}
+
+/// Open VS Code where the run landed, at its file and line when given.
+fn open_editor(cli: &Cli, landing: &Landing, out: &mut dyn Write) {
+    let result = match &landing.goto {
+        Some((file, line)) => {
+            git::open_file_in_vscode_with_writer(&landing.dir, file, *line, cli.dry_run, out)
+        }
+        None => git::open_in_vscode_with_writer(&landing.dir, cli.dry_run, out),
+    };
+    if let Err(e) = result {
+        eprintln!(
+            "{}",
+            format!("Warning: failed to open VS Code: {}", e).yellow()
+        );
+    }
+}
+
+/// Expand an `owner/repo` command target to a URL on the default host,
+/// unless a directory of that name exists.
+fn expand_target(config: &config::Config, target: &str) -> String {
+    if Path::new(target).is_dir() {
+        return target.to_string();
+    }
+    spec::expand_shorthand(target, &config.default_host).unwrap_or_else(|| target.to_string())
+}
+
+/// Load the user configuration, exiting on a malformed file.
+fn load_config() -> config::Config {
+    match config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", format!("Error loading config: {:#}", e).red());
+            process::exit(1);
+        }
+    }
+}
+
+/// Build the directory layout from the user configuration and flags.
+fn layout(cli: &Cli, config: &config::Config) -> fs::Layout {
+    fs::Layout::new(config, cli.full_host, cli.include_port, cli.lowercase_owner)
+}
+
+/// Write one line of human-readable output, ignoring closed pipes.
+fn say(out: &mut dyn Write, line: impl std::fmt::Display) {
+    let _ = writeln!(out, "{}", line);
+}
+
+fn print_json(report: &RunReport) {
+    match serde_json::to_string_pretty(report) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("{}", format!("Error encoding JSON: {}", e).red());
+            process::exit(1);
+        }
+    }
+}
+
+/// Print the doctor report; exit non-zero when a check failed.
+fn doctor(cli: &Cli) {
+    let checks = doctor::run(&cli.root, &layout(cli, &load_config()));
+    for check in &checks {
+        let status = format!("[{}]", check.status);
+        let status = match check.status {
+            doctor::Status::Pass => status.green().to_string(),
+            doctor::Status::Warn => status.yellow().to_string(),
+            doctor::Status::Fail => status.red().to_string(),
+        };
+        println!("{} {:<12} {}", status, check.name, check.detail);
+        if let Some(hint) = &check.hint {
+            println!("       {:<12} hint: {}", "", hint);
+        }
+    }
+    if checks.iter().any(|c| c.status == doctor::Status::Fail) {
+        process::exit(1);
+    }
+}
+
+/// Move repositories that were renamed or transferred upstream to where the
+/// layout puts their current origin URL.
+fn relocate(cli: &Cli, target: Option<&str>, yes: bool) {
+    let config = load_config();
+    let layout = layout(cli, &config);
+    let root = match fs::expand_root(&cli.root) {
+        Ok(root) => root,
+        Err(e) => {
+            eprintln!("{}", format!("Error resolving root: {}", e).red());
+            process::exit(1);
+        }
+    };
+    let repos = match target {
+        Some(t) => match fs::resolve_mirror(&cli.root, &expand_target(&config, t), &layout) {
+            Ok(p) => vec![p],
+            Err(e) => {
+                eprintln!("{}", format!("Error building local path: {}", e).red());
+                process::exit(1);
+            }
+        },
+        None => fs::find_repos(&root),
+    };
+
+    let mut failed = false;
+    let mut origins = Vec::new();
+    for repo in repos {
+        if !git::repo_exists(&repo) {
+            eprintln!(
+                "{}",
+                format!("Not a git repository: {}", repo.display()).red()
+            );
+            failed = true;
+            continue;
+        }
+        // A linked worktree moves with nothing; its repository is checked instead
+        if git::is_linked_worktree(&repo) {
+            println!("Skipping linked worktree {}", repo.display());
+            continue;
+        }
+        match git::origin_url(&repo) {
+            Ok(Some(url)) if layout.local_path(&cli.root, &url).is_ok() => {
+                origins.push((repo, url))
+            }
+            Ok(Some(url)) => eprintln!(
+                "{}",
+                format!(
+                    "Skipping {}: cannot lay out origin {}",
+                    repo.display(),
+                    util::sanitize_url(&url)
+                )
+                .yellow()
+            ),
+            Ok(None) => println!("Skipping {}: no origin remote", repo.display()),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    format!("Error inspecting {}: {}", repo.display(), e).red()
+                );
+                failed = true;
+            }
+        }
+    }
+
+    let plan = match fs::plan_relocations(&cli.root, &origins, &layout) {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("{}", format!("Error planning moves: {:#}", e).red());
+            process::exit(1);
+        }
+    };
+    if plan.is_empty() {
+        println!("{}", "Every repository matches its origin URL".green());
+    }
+    for relocation in &plan {
+        println!(
+            "{} -> {}",
+            relocation.from.display().to_string().cyan(),
+            relocation.to.display()
+        );
+        if let Some(conflict) = &relocation.conflict {
+            println!("{}", format!("  conflict: {}", conflict).red());
+            failed = true;
+        }
+    }
+
+    let moves: Vec<_> = plan.iter().filter(|r| r.conflict.is_none()).collect();
+    if cli.dry_run {
+        println!("Dry run: {} repositor(y/ies) to move", moves.len());
+    } else if !moves.is_empty() {
+        let confirmed = yes
+            || (!cli.no_prompt
+                && Confirm::new()
+                    .with_prompt(format!("Move {} repositor(y/ies)?", moves.len()))
+                    .default(false)
+                    .interact()
+                    .unwrap_or(false));
+        if !confirmed {
+            println!("Nothing moved");
+        }
+        for relocation in moves.into_iter().filter(|_| confirmed) {
+            if let Err(e) = fs::relocate_repo(&root, &relocation.from, &relocation.to) {
+                eprintln!("{}", format!("Error: {:#}", e).red());
+                failed = true;
+                continue;
+            }
+            if let Err(e) = git::repair_worktrees(&relocation.to) {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Warning: failed to re-link worktrees of {}: {}",
+                        relocation.to.display(),
+                        e
+                    )
+                    .yellow()
+                );
+            }
+            println!(
+                "{}",
+                format!("Moved to {}", relocation.to.display()).green()
+            );
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Delete one repository after checking it holds no work that exists only
+/// locally.
+fn remove(cli: &Cli, target: &str, force: bool, yes: bool) {
+    let config = load_config();
+    let target = expand_target(&config, target);
+    let repo = match fs::resolve_mirror(&cli.root, &target, &layout(cli, &config)) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", format!("Error building local path: {}", e).red());
+            process::exit(1);
+        }
+    };
+    if !git::repo_exists(&repo) {
+        eprintln!(
+            "{}",
+            format!("Not a git repository: {}", repo.display()).red()
+        );
+        process::exit(1);
+    }
+
+    let unsaved = match git::unsaved_work(&repo) {
+        Ok(u) => u,
+        Err(e) if force => {
+            eprintln!(
+                "{}",
+                format!("Warning: could not inspect {}: {}", repo.display(), e).yellow()
+            );
+            Vec::new()
+        }
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Error inspecting {}: {} (use --force to remove anyway)",
+                    repo.display(),
+                    e
+                )
+                .red()
+            );
+            process::exit(1);
+        }
+    };
+    if !unsaved.is_empty() {
+        let heading = format!("{} has work that exists only locally:", repo.display());
+        if force {
+            eprintln!("{}", heading.yellow());
+        } else {
+            eprintln!("{}", heading.red());
+        }
+        for work in &unsaved {
+            eprintln!("  {}", work);
+        }
+        if !force {
+            eprintln!("{}", "Refusing to remove it; use --force to override".red());
+            process::exit(1);
+        }
+    }
+
+    let root = fs::expand_root(&cli.root).unwrap_or_else(|_| PathBuf::from(&cli.root));
+    let planned = match fs::remove_repo(&root, &repo, true) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", format!("Error: {:#}", e).red());
+            process::exit(1);
+        }
+    };
+    for dir in &planned {
+        println!("{}", dir.display().to_string().cyan());
+    }
+    if cli.dry_run {
+        println!("Dry run: remove {} director(y/ies)", planned.len());
+        return;
+    }
+
+    let confirmed = yes
+        || (!cli.no_prompt
+            && Confirm::new()
+                .with_prompt(format!("Remove {}?", repo.display()))
+                .default(false)
+                .interact()
+                .unwrap_or(false));
+    if !confirmed {
+        println!("Nothing removed");
+        return;
+    }
+
+    match fs::remove_repo(&root, &repo, false) {
+        Ok(removed) => println!(
+            "{}",
+            format!("Removed {} director(y/ies)", removed.len()).green()
+        ),
+        Err(e) => {
+            eprintln!("{}", format!("Error: {:#}", e).red());
+            process::exit(1);
+        }
+    }
+}
+
+/// Print the repositories `owner` has on a forge, marking the ones mirrored
+/// below the root.
+fn list(cli: &Cli, owner: &str, host: Option<&str>) {
+    let config = load_config();
+    let layout = layout(cli, &config);
+    let host = host.unwrap_or(&config.default_host);
+    let mut repos = match layout.forges.forge_for_host(host).list_repos(host, owner) {
+        Ok(repos) => repos,
+        Err(e) => {
+            eprintln!("{}", format!("Error listing repositories: {:#}", e).red());
+            process::exit(1);
+        }
+    };
+    repos.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut mirrored = 0;
+    for repo in &repos {
+        let present = layout
+            .local_path(&cli.root, &repo.clone_url)
+            .is_ok_and(|p| git::repo_exists(&p));
+        let mut line = format!(
+            "{:<8} {}",
+            if present { "mirrored" } else { "missing" },
+            repo.path
+        );
+        if repo.archived {
+            line.push_str(" (archived)");
+        }
+        if let Some(description) = &repo.description {
+            line.push_str(&format!(" - {}", description));
+        }
+        if present {
+            mirrored += 1;
+            println!("{}", line.green());
+        } else {
+            println!("{}", line);
+        }
+    }
+    println!(
+        "{}",
+        format!("{} of {} repositories mirrored", mirrored, repos.len()).cyan()
+    );
+}
+
+/// Build the forge web URL for `path` and open or print it.
+fn browse(
+    cli: &Cli,
+    path: &Path,
+    branch: bool,
+    commit: bool,
+    line: Option<u32>,
+    print: bool,
+) -> anyhow::Result<()> {
+    let location = git::locate(path)?;
+    let origin = git::origin_url(&location.root)?
+        .ok_or_else(|| anyhow::anyhow!("{} has no origin remote", location.root.display()))?;
+    let spec = spec::RepoSpec::parse(&origin)?;
+    if line.is_some() && location.file.is_none() {
+        anyhow::bail!("--line needs a file to point at");
+    }
+
+    // Pin to the commit when asked, or when there is no branch to name
+    let rev = match &location.branch {
+        Some(b) if !commit => forge::WebRev::Branch(b),
+        _ => forge::WebRev::Commit(&location.head),
+    };
+    let target = match &location.file {
+        Some(file) => forge::WebTarget::File {
+            rev,
+            path: file,
+            line,
+        },
+        None if commit => forge::WebTarget::Commit(&location.head),
+        None if branch => match &location.branch {
+            Some(b) => forge::WebTarget::Branch(b),
+            None => forge::WebTarget::Commit(&location.head),
+        },
+        None => forge::WebTarget::Repo,
+    };
+
+    let layout = layout(cli, &load_config());
+    let url = match layout.forges.web_url(&spec, &layout.ssh, target) {
+        Some(url) => url,
+        None => {
+            let url = layout
+                .forges
+                .web_url(&spec, &layout.ssh, forge::WebTarget::Repo)
+                .ok_or_else(|| anyhow::anyhow!("no web page is known for {}", spec))?;
+            eprintln!(
+                "{}",
+                format!(
+                    "Warning: {} has no page for this target; using the repository page",
+                    spec.host
+                )
+                .yellow()
+            );
+            url
+        }
+    };
+
+    if print {
+        println!("{}", url);
+        return Ok(());
+    }
+    git::open_in_browser_with_writer(&url, cli.dry_run, &mut io::stdout())
+}
+
+/// Handle `git-mirror prune-branches`: list prunable branches per repository
+/// and delete them after confirmation (or straight away with `--yes`).
+fn prune_branches(cli: &Cli, target: Option<&str>, yes: bool) {
+    let config = load_config();
+    let repos = match target {
+        Some(t) => {
+            match fs::resolve_mirror(&cli.root, &expand_target(&config, t), &layout(cli, &config)) {
+                Ok(p) => vec![p],
+                Err(e) => {
+                    eprintln!("{}", format!("Error building local path: {}", e).red());
+                    process::exit(1);
+                }
+            }
+        }
+        None => match fs::expand_root(&cli.root) {
+            Ok(root) => fs::find_repos(&root),
+            Err(e) => {
+                eprintln!("{}", format!("Error resolving root: {}", e).red());
+                process::exit(1);
+            }
+        },
+    };
+
+    let mut failed = false;
+    for repo in repos {
+        if !git::repo_exists(&repo) {
+            eprintln!(
+                "{}",
+                format!("Not a git repository: {}", repo.display()).red()
+            );
+            failed = true;
+            continue;
+        }
+
+        let candidates = match git::prunable_branches(&repo) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    format!("Error inspecting {}: {}", repo.display(), e).red()
+                );
+                failed = true;
+                continue;
+            }
+        };
+        if candidates.is_empty() {
+            continue;
+        }
+
+        println!("{}", repo.display().to_string().cyan());
+        for (branch, reason) in &candidates {
+            println!("  {} ({})", branch, reason);
+        }
+
+        let confirmed = yes
+            || cli.dry_run
+            || (!cli.no_prompt
+                && Confirm::new()
+                    .with_prompt(format!("Delete {} branch(es)?", candidates.len()))
+                    .default(false)
+                    .interact()
+                    .unwrap_or(false));
+        if !confirmed {
+            continue;
+        }
+
+        for (branch, _) in &candidates {
+            match git::delete_branch_with_writer(&repo, branch, cli.dry_run, &mut io::stdout()) {
+                Ok(()) if !cli.dry_run => {
+                    println!("{}", format!("  Deleted {}", branch).green())
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    eprintln!("{}", format!("  Failed to delete {}: {}", branch, e).red());
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,156 @@
+use clap_complete::engine::{CompletionCandidate, PathCompleter, ValueCompleter};
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::fs::{expand_root, find_repos, Layout};
+use crate::git;
+
+/// Complete a repository argument: `owner/repo` shorthands for repositories
+/// mirrored from the default host below `root`, origin URLs once the input
+/// looks like a URL, and directories for input that looks like a path.
+pub fn complete_repo(
+    current: &OsStr,
+    root: &str,
+    config: &Config,
+    layout: &Layout,
+) -> Vec<CompletionCandidate> {
+    let Some(prefix) = current.to_str() else {
+        return Vec::new();
+    };
+    if prefix.starts_with(['.', '/', '~']) {
+        return PathCompleter::dir().complete(current);
+    }
+    candidates(root, config, layout, prefix)
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Repository names under `root` that start with `prefix`: shorthands first,
+/// origin URLs when the input is a URL or no shorthand matches.
+fn candidates(root: &str, config: &Config, layout: &Layout, prefix: &str) -> Vec<String> {
+    let Ok(root_dir) = expand_root(root) else {
+        return Vec::new();
+    };
+    let repos = if root_dir.is_dir() {
+        find_repos(&root_dir)
+    } else {
+        Vec::new()
+    };
+
+    let matching = |names: BTreeSet<String>| -> Vec<String> {
+        names
+            .into_iter()
+            .filter(|c| c.starts_with(prefix))
+            .collect()
+    };
+    if !prefix.contains([':', '@']) {
+        let shorthands = matching(shorthands(root, config, layout, &repos));
+        if !shorthands.is_empty() || prefix.is_empty() {
+            return shorthands;
+        }
+    }
+    // Asking git for every origin is slow, so it's left for input that
+    // isn't a shorthand
+    matching(
+        repos
+            .iter()
+            .filter_map(|repo| git::origin_url(repo).ok().flatten())
+            .collect(),
+    )
+}
+
+/// `owner/repo` for every repository laid out under the default host.
+fn shorthands(root: &str, config: &Config, layout: &Layout, repos: &[PathBuf]) -> BTreeSet<String> {
+    let Some(host_dir) = default_host_dir(root, config, layout) else {
+        return BTreeSet::new();
+    };
+    repos
+        .iter()
+        .filter_map(|repo| {
+            let rel = repo.strip_prefix(&host_dir).ok()?;
+            let parts: Vec<_> = rel.iter().map(|p| p.to_string_lossy()).collect();
+            match parts.as_slice() {
+                [owner, name] => Some(format!("{}/{}", owner, name)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The directory repositories on the default host are laid out under.
+fn default_host_dir(root: &str, config: &Config, layout: &Layout) -> Option<PathBuf> {
+    let probe = format!("https://{}/owner/repo", config.default_host);
+    let path = layout.local_path(root, &probe).ok()?;
+    path.parent()?.parent().map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::candidates;
+    use crate::config::Config;
+    use crate::fs::Layout;
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn test_candidates() {
+        let mut root = env::temp_dir();
+        root.push("git_mirror_completions");
+        let _ = fs::remove_dir_all(&root);
+        for (dir, origin) in [
+            ("github/octo/hello", "git@github.com:octo/hello.git"),
+            ("github/octo/world", "https://github.com/octo/world.git"),
+            ("gitlab/group/sub/app", "git@gitlab.com:group/sub/app.git"),
+        ] {
+            let repo = root.join(dir);
+            fs::create_dir_all(&repo).expect("create repo");
+            for args in [vec!["init", "-q"], vec!["remote", "add", "origin", origin]] {
+                let status = Command::new("git")
+                    .arg("-C")
+                    .arg(&repo)
+                    .args(&args)
+                    .status()
+                    .expect("run git");
+                assert!(status.success());
+            }
+        }
+        let root_str = root.to_str().unwrap();
+        let config = Config::default();
+        let layout = Layout::default();
+
+        assert_eq!(
+            candidates(root_str, &config, &layout, ""),
+            vec!["octo/hello", "octo/world"]
+        );
+        assert_eq!(
+            candidates(root_str, &config, &layout, "octo/w"),
+            vec!["octo/world"]
+        );
+        assert_eq!(
+            candidates(root_str, &config, &layout, "git@"),
+            vec![
+                "git@github.com:octo/hello.git",
+                "git@gitlab.com:group/sub/app.git"
+            ]
+        );
+        assert_eq!(
+            candidates(root_str, &config, &layout, "https:"),
+            vec!["https://github.com/octo/world.git"]
+        );
+        assert_eq!(
+            candidates(root_str, &config, &layout, "htt"),
+            vec!["https://github.com/octo/world.git"]
+        );
+
+        let gitlab = Config {
+            default_host: "gitlab.com".to_string(),
+            ..Config::default()
+        };
+        assert!(candidates(root_str, &gitlab, &layout, "").is_empty());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
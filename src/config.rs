@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::forge::ForgeKind;
+
+/// User configuration read from `~/.config/git-mirror/config.toml`.
+///
+/// Example:
+///
+/// ```toml
+/// default_host = "gitlab.com"
+///
+/// [forges]
+/// "git.corp.example" = "gitlab"
+/// "code.example.org" = "gitea"
+///
+/// [local]
+/// host = "nfs"
+/// strip_prefix = "/srv/git"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Host that `owner/repo` shorthands refer to
+    pub default_host: String,
+    /// Self-hosted forges keyed by host name
+    pub forges: BTreeMap<String, ForgeKind>,
+    /// Where `file://` URLs and local paths are placed in the layout
+    pub local: LocalConfig,
+}
+
+/// Layout mapping for local sources: `<root>/<host>/<path>`, with
+/// `strip_prefix` removed from the front of the source path when it matches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalConfig {
+    /// Pseudo-host directory local sources are placed under
+    pub host: String,
+    pub strip_prefix: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            default_host: "github.com".to_string(),
+            forges: BTreeMap::new(),
+            local: LocalConfig::default(),
+        }
+    }
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        LocalConfig {
+            host: "local".to_string(),
+            strip_prefix: None,
+        }
+    }
+}
+
+/// Return the config file location. `GIT_MIRROR_CONFIG` overrides the default
+/// `<config dir>/git-mirror/config.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(p) = std::env::var_os("GIT_MIRROR_CONFIG") {
+        return Some(PathBuf::from(p));
+    }
+    dirs::config_dir().map(|d| d.join("git-mirror").join("config.toml"))
+}
+
+/// Load the user configuration. A missing file yields the default config.
+pub fn load() -> Result<Config> {
+    match config_path() {
+        Some(path) if path.exists() => load_from(&path),
+        _ => Ok(Config::default()),
+    }
+}
+
+/// Load and parse the configuration at `path`.
+pub fn load_from(path: &Path) -> Result<Config> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    parse(&text).map_err(|err| {
+        Error::Config {
+            path: path.to_path_buf(),
+            message: format!("{:#}", err),
+        }
+        .into()
+    })
+}
+
+fn parse(text: &str) -> Result<Config> {
+    Ok(toml::from_str(text)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::forge::ForgeKind;
+
+    #[test]
+    fn test_parse_empty() {
+        let cfg = parse("").expect("parse");
+        assert!(cfg.forges.is_empty());
+        assert_eq!(cfg.default_host, "github.com");
+        assert_eq!(cfg.local.host, "local");
+        assert!(cfg.local.strip_prefix.is_none());
+    }
+
+    #[test]
+    fn test_parse_forges() {
+        let cfg = parse(
+            r#"
+            [forges]
+            "git.corp.example" = "gitlab"
+            "code.example.org" = "gitea"
+            "tfs.example.com" = "azure-devops"
+            "#,
+        )
+        .expect("parse");
+        assert_eq!(cfg.forges["git.corp.example"], ForgeKind::GitLab);
+        assert_eq!(cfg.forges["code.example.org"], ForgeKind::Gitea);
+        assert_eq!(cfg.forges["tfs.example.com"], ForgeKind::AzureDevOps);
+    }
+
+    #[test]
+    fn test_parse_default_host() {
+        let cfg = parse("default_host = \"gitlab.com\"\n").expect("parse");
+        assert_eq!(cfg.default_host, "gitlab.com");
+    }
+
+    #[test]
+    fn test_parse_local() {
+        let cfg = parse("[local]\nhost = \"nfs\"\nstrip_prefix = \"/srv/git\"\n").expect("parse");
+        assert_eq!(cfg.local.host, "nfs");
+        assert_eq!(
+            cfg.local.strip_prefix.as_deref(),
+            Some(std::path::Path::new("/srv/git"))
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_forge_kind() {
+        assert!(parse("[forges]\n\"h.example\" = \"sourcehut\"\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_unknown_key() {
+        assert!(parse("colour = true\n").is_err());
+    }
+}
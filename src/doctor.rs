@@ -0,0 +1,452 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use which::which;
+
+use crate::config;
+use crate::fs::{expand_root, find_repos, plan_relocations, Layout};
+use crate::git;
+use crate::spec::{RepoSpec, Scheme};
+
+/// Oldest git with every command git-mirror runs (`git worktree repair`).
+const MIN_GIT_VERSION: (u32, u32) = (2, 29);
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Pass => write!(f, "pass"),
+            Status::Warn => write!(f, "warn"),
+            Status::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// One line of the doctor report, with a remediation hint when it didn't pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Check {
+        Check {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Check {
+        Check {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Check {
+        Check {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Run every check against the environment, the root and its repositories.
+pub fn run(root: &str, layout: &Layout) -> Vec<Check> {
+    let mut checks = vec![check_git(), check_editor(), check_browser_opener()];
+    checks.push(check_config(config::config_path().as_deref()));
+    let root_dir = expand_root(root).ok();
+    checks.push(check_root(root_dir.as_deref()));
+    checks.push(check_ssh());
+
+    // Origins of the mirrored repositories drive the per-host checks
+    let repos: Vec<(PathBuf, String)> = root_dir
+        .as_deref()
+        .filter(|r| r.is_dir())
+        .map(find_repos)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|repo| {
+            let origin = git::origin_url(&repo).ok().flatten()?;
+            Some((repo, origin))
+        })
+        .collect();
+    checks.extend(check_credential_helpers(&repos));
+    checks.push(check_layout(root, &repos, layout));
+    checks
+}
+
+/// Parse `git --version` output such as `git version 2.39.3 (Apple Git-146)`.
+fn parse_git_version(output: &str) -> Option<(u32, u32)> {
+    let version = output
+        .strip_prefix("git version ")?
+        .split_whitespace()
+        .next()?;
+    let mut parts = version.split('.').map(|p| p.parse::<u32>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => Some((major, minor)),
+        _ => None,
+    }
+}
+
+fn check_git() -> Check {
+    const NAME: &str = "git";
+    let Ok(git) = which("git") else {
+        return Check::fail(
+            NAME,
+            "git executable not found in PATH",
+            "install git (https://git-scm.com/downloads) and make sure it is on PATH",
+        );
+    };
+    let output = match Command::new(&git).arg("--version").output() {
+        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).trim().to_string(),
+        _ => {
+            return Check::fail(
+                NAME,
+                format!("{} --version failed", git.display()),
+                "reinstall git",
+            )
+        }
+    };
+    match parse_git_version(&output) {
+        Some(version) if version >= MIN_GIT_VERSION => Check::pass(NAME, output),
+        Some(_) => Check::warn(
+            NAME,
+            output,
+            format!(
+                "upgrade to git {}.{} or newer; relocating repositories with worktrees needs it",
+                MIN_GIT_VERSION.0, MIN_GIT_VERSION.1
+            ),
+        ),
+        None => Check::warn(
+            NAME,
+            format!("unrecognised version: {}", output),
+            "make sure `git` is git and not a wrapper",
+        ),
+    }
+}
+
+fn check_editor() -> Check {
+    const NAME: &str = "editor";
+    match which("code") {
+        Ok(path) => Check::pass(NAME, format!("VS Code at {}", path.display())),
+        Err(_) => Check::warn(
+            NAME,
+            "`code` not found in PATH",
+            "install VS Code and run \"Shell Command: Install 'code' command in PATH\", \
+             or pass --no-open-vs-code",
+        ),
+    }
+}
+
+fn check_browser_opener() -> Check {
+    const NAME: &str = "browser";
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "rundll32"
+    } else {
+        "xdg-open"
+    };
+    match which(opener) {
+        Ok(path) => Check::pass(NAME, format!("{} at {}", opener, path.display())),
+        Err(_) => Check::warn(
+            NAME,
+            format!("`{}` not found in PATH", opener),
+            "install xdg-utils, or use `git-mirror browse --print`",
+        ),
+    }
+}
+
+fn check_config(path: Option<&Path>) -> Check {
+    const NAME: &str = "config";
+    match path {
+        Some(path) if path.exists() => match config::load_from(path) {
+            Ok(_) => Check::pass(NAME, path.display().to_string()),
+            Err(e) => Check::fail(
+                NAME,
+                format!("{:#}", e),
+                "fix or remove the file; see the Configuration section of the README",
+            ),
+        },
+        Some(path) => Check::pass(
+            NAME,
+            format!("no file at {}; using defaults", path.display()),
+        ),
+        None => Check::pass(NAME, "no config directory; using defaults"),
+    }
+}
+
+fn check_root(root: Option<&Path>) -> Check {
+    const NAME: &str = "root";
+    let Some(root) = root else {
+        return Check::fail(
+            NAME,
+            "cannot resolve the root directory",
+            "set HOME or pass an absolute --root",
+        );
+    };
+    if !root.exists() {
+        return Check::warn(
+            NAME,
+            format!("{} does not exist", root.display()),
+            "it is created by the first clone; pass --root if this is not where you keep projects",
+        );
+    }
+    if !root.is_dir() {
+        return Check::fail(
+            NAME,
+            format!("{} is not a directory", root.display()),
+            "move the file away or pass a different --root",
+        );
+    }
+    let probe = root.join(format!(".git-mirror-doctor-{}", std::process::id()));
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Check::pass(NAME, format!("{} is writable", root.display()))
+        }
+        Err(e) => Check::fail(
+            NAME,
+            format!("{} is not writable: {}", root.display(), e),
+            "fix the directory's permissions or pass a different --root",
+        ),
+    }
+}
+
+fn check_ssh() -> Check {
+    const NAME: &str = "ssh";
+    let key_files: Vec<String> = dirs::home_dir()
+        .map(|h| h.join(".ssh"))
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .filter(|n| n.starts_with("id_") && !n.ends_with(".pub"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // ssh-add -l: 0 lists keys, 1 means an agent without keys, 2 no agent
+    let agent = std::env::var_os("SSH_AUTH_SOCK").and_then(|_| {
+        let ssh_add = which("ssh-add").ok()?;
+        Command::new(ssh_add).arg("-l").output().ok()?.status.code()
+    });
+    match (agent, key_files.is_empty()) {
+        (Some(0), _) => Check::pass(NAME, "ssh-agent is running with keys loaded"),
+        (Some(1), false) => Check::warn(
+            NAME,
+            format!("ssh-agent has no keys; found {}", key_files.join(", ")),
+            "run `ssh-add` to avoid a passphrase prompt per clone",
+        ),
+        (_, false) => Check::pass(NAME, format!("keys: {}", key_files.join(", "))),
+        (_, true) => Check::warn(
+            NAME,
+            "no ssh-agent keys and no ~/.ssh/id_* key files",
+            "create a key with `ssh-keygen -t ed25519` and add it to your forge, \
+             or clone over https",
+        ),
+    }
+}
+
+/// Credential helper git uses for `url`, honouring `credential.<url>.helper`.
+fn credential_helper(url: &str) -> Option<String> {
+    let git = which("git").ok()?;
+    let output = Command::new(git)
+        .args(["config", "--get-urlmatch", "credential.helper", url])
+        .output()
+        .ok()?;
+    let helper = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !helper.is_empty()).then_some(helper)
+}
+
+/// https hosts without a credential helper prompt for a password on every
+/// fetch of a private repository.
+fn check_credential_helpers(repos: &[(PathBuf, String)]) -> Vec<Check> {
+    const NAME: &str = "credentials";
+    let mut hosts: BTreeMap<String, usize> = BTreeMap::new();
+    for (_, origin) in repos {
+        if let Ok(spec) = RepoSpec::parse(origin) {
+            if matches!(spec.scheme, Scheme::Https | Scheme::Http) {
+                let host = match spec.port {
+                    Some(port) => format!("{}:{}", spec.host, port),
+                    None => spec.host.clone(),
+                };
+                *hosts.entry(host).or_default() += 1;
+            }
+        }
+    }
+    hosts
+        .into_iter()
+        .map(
+            |(host, count)| match credential_helper(&format!("https://{}/", host)) {
+                Some(helper) => Check::pass(NAME, format!("{}: {}", host, helper)),
+                None => Check::warn(
+                    NAME,
+                    format!("{}: no credential helper ({} repositories)", host, count),
+                    format!(
+                        "configure one, e.g. `git config --global credential.https://{}.helper store`, \
+                         or use ssh URLs",
+                        host
+                    ),
+                ),
+            },
+        )
+        .collect()
+}
+
+/// Repositories whose directory doesn't match their origin, and directories
+/// that only differ in case (one directory on case-insensitive filesystems).
+fn check_layout(root: &str, repos: &[(PathBuf, String)], layout: &Layout) -> Check {
+    const NAME: &str = "layout";
+    let plan = match plan_relocations(root, repos, layout) {
+        Ok(plan) => plan,
+        Err(e) => return Check::fail(NAME, format!("{:#}", e), "check the --root value"),
+    };
+    let conflicts = plan.iter().filter(|r| r.conflict.is_some()).count();
+    if conflicts > 0 {
+        return Check::fail(
+            NAME,
+            format!(
+                "{} repositories collide with another path in the layout",
+                conflicts
+            ),
+            "run `git-mirror relocate --dry-run` to see the collisions",
+        );
+    }
+
+    let mut by_lowercase: BTreeMap<String, Vec<&Path>> = BTreeMap::new();
+    for (repo, _) in repos {
+        by_lowercase
+            .entry(repo.to_string_lossy().to_lowercase())
+            .or_default()
+            .push(repo);
+    }
+    if let Some(paths) = by_lowercase.values().find(|p| p.len() > 1) {
+        return Check::warn(
+            NAME,
+            format!(
+                "{} and {} differ only in case",
+                paths[0].display(),
+                paths[1].display()
+            ),
+            "use --lowercase-owner and remove the duplicate",
+        );
+    }
+    if !plan.is_empty() {
+        return Check::warn(
+            NAME,
+            format!(
+                "{} repositories are not where their origin URL puts them",
+                plan.len()
+            ),
+            "run `git-mirror relocate` to move them",
+        );
+    }
+    Check::pass(NAME, format!("{} repositories in place", repos.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_config, check_layout, check_root, parse_git_version, Status};
+    use crate::fs::Layout;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_git_version() {
+        assert_eq!(parse_git_version("git version 2.43.0"), Some((2, 43)));
+        assert_eq!(
+            parse_git_version("git version 2.39.3 (Apple Git-146)"),
+            Some((2, 39))
+        );
+        assert_eq!(
+            parse_git_version("git version 2.45.1.windows.1"),
+            Some((2, 45))
+        );
+        assert_eq!(parse_git_version("hub version 2.14.2"), None);
+    }
+
+    #[test]
+    fn test_check_root_and_config() {
+        let mut dir = env::temp_dir();
+        dir.push("git_mirror_doctor_root");
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(check_root(Some(&dir)).status, Status::Warn);
+        fs::create_dir_all(&dir).expect("create dir");
+        assert_eq!(check_root(Some(&dir)).status, Status::Pass);
+        assert_eq!(check_root(None).status, Status::Fail);
+
+        let config = dir.join("config.toml");
+        assert_eq!(check_config(Some(&config)).status, Status::Pass);
+        fs::write(&config, "[forges]\n\"h\" = \"sourcehut\"\n").expect("write");
+        let check = check_config(Some(&config));
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.hint.is_some());
+        fs::write(&config, "default_host = \"gitlab.com\"\n").expect("write");
+        assert_eq!(check_config(Some(&config)).status, Status::Pass);
+
+        fs::write(dir.join("file"), "").expect("write");
+        assert_eq!(check_root(Some(&dir.join("file"))).status, Status::Fail);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_check_layout() {
+        let mut root = env::temp_dir();
+        root.push("git_mirror_doctor_layout");
+        let _ = fs::remove_dir_all(&root);
+        let root_str = root.to_str().unwrap().to_string();
+        let repo = |dir: &str, url: &str| {
+            fs::create_dir_all(root.join(dir).join(".git")).expect("create repo");
+            (root.join(dir), url.to_string())
+        };
+        let placed = vec![repo("github/o/a", "git@github.com:o/a.git")];
+        assert_eq!(
+            check_layout(&root_str, &placed, &Layout::default()).status,
+            Status::Pass
+        );
+
+        let mut repos: Vec<(PathBuf, String)> = placed.clone();
+        repos.push(repo("github/old/b", "git@github.com:o/b.git"));
+        assert_eq!(
+            check_layout(&root_str, &repos, &Layout::default()).status,
+            Status::Warn
+        );
+
+        repos.push(repo("github/other/b", "https://github.com/o/b"));
+        assert_eq!(
+            check_layout(&root_str, &repos, &Layout::default()).status,
+            Status::Fail
+        );
+
+        let cased = vec![
+            repo("github/Owner/c", "git@github.com:Owner/c.git"),
+            repo("github/owner/c", "git@github.com:owner/c.git"),
+        ];
+        let check = check_layout(&root_str, &cased, &Layout::default());
+        assert_eq!(check.status, Status::Warn);
+        assert!(check.detail.contains("differ only in case"));
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+/// Errors returned by the library's public API.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input isn't a repository URL or path git-mirror understands
+    #[error("Invalid Git repository URL: {0}")]
+    InvalidUrl(String),
+    /// A URL component would place the repository outside the root
+    #[error("Refusing unsafe repository path in {repo}: {reason}")]
+    UnsafePath { repo: String, reason: String },
+    #[error("{} already exists and isn't an empty directory", path.display())]
+    TargetExists { path: PathBuf },
+    #[error("{} is not a mirrored repository", path.display())]
+    NotMirrored { path: PathBuf },
+    #[error("git executable not found in PATH")]
+    GitNotFound,
+    /// A git command exited unsuccessfully; `stderr` is what it printed
+    #[error("git {command} failed: {stderr}")]
+    Git { command: String, stderr: String },
+    /// A git command that reports to the terminal exited unsuccessfully
+    #[error("git {command} failed with status: {status}")]
+    GitStatus { command: String, status: ExitStatus },
+    /// Ctrl-C stopped a clone; the partial clone has been removed
+    #[error("git clone interrupted")]
+    Interrupted,
+    #[error("invalid config file {}: {message}", path.display())]
+    Config { path: PathBuf, message: String },
+    /// Anything else, such as a filesystem error, with its context chain
+    #[error("{0:#}")]
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<anyhow::Error> for Error {
+    /// Recover the typed error an internal helper raised, if any.
+    fn from(err: anyhow::Error) -> Error {
+        err.downcast::<Error>().unwrap_or_else(Error::Other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use anyhow::Context;
+
+    #[test]
+    fn test_from_anyhow_recovers_typed_errors() {
+        let err = Err::<(), _>(Error::GitNotFound)
+            .context("fetching")
+            .unwrap_err();
+        assert!(matches!(Error::from(err), Error::GitNotFound));
+
+        let err = Error::from(anyhow::anyhow!("disk full"));
+        assert!(matches!(err, Error::Other(_)));
+        assert_eq!(err.to_string(), "disk full");
+    }
+}
@@ -0,0 +1,1499 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::process::Command;
+use which::which;
+
+use crate::config::Config;
+use crate::spec::{RepoSpec, Scheme};
+use crate::ssh_config::SshConfig;
+use url::Url;
+
+/// The family of hosting software serving a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+pub enum ForgeKind {
+    #[serde(rename = "github")]
+    GitHub,
+    #[serde(rename = "gitlab")]
+    GitLab,
+    #[serde(rename = "bitbucket")]
+    Bitbucket,
+    #[serde(rename = "gitea", alias = "forgejo")]
+    Gitea,
+    #[serde(rename = "azure-devops", alias = "azure")]
+    AzureDevOps,
+    #[serde(rename = "generic")]
+    Generic,
+}
+
+/// A repository as reported by a forge API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteRepo {
+    /// Repository path below the host, e.g. `owner/repo`
+    pub path: String,
+    pub clone_url: String,
+    pub description: Option<String>,
+    pub archived: bool,
+}
+
+/// What a web URL should point at within a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebTarget<'a> {
+    Repo,
+    Branch(&'a str),
+    File {
+        rev: WebRev<'a>,
+        path: &'a str,
+        line: Option<u32>,
+    },
+    Commit(&'a str),
+}
+
+/// The revision a file link is pinned to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebRev<'a> {
+    Branch(&'a str),
+    Commit(&'a str),
+}
+
+impl<'a> WebRev<'a> {
+    fn name(self) -> &'a str {
+        match self {
+            WebRev::Branch(name) | WebRev::Commit(name) => name,
+        }
+    }
+}
+
+/// A browser URL pointing into a repository, split into the repository and
+/// the page within it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebLink {
+    /// Path segments of the repository itself, e.g. `["owner", "repo"]`
+    pub repo: Vec<String>,
+    pub target: WebLinkTarget,
+}
+
+/// The page a [`WebLink`] points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebLinkTarget {
+    /// A branch, tag or commit followed by an optional path. Branch names may
+    /// contain `/`, so where the ref ends is only known once the remote's
+    /// refs are available.
+    Ref {
+        rest: Vec<String>,
+        line: Option<u32>,
+    },
+    Commit(String),
+    /// A pull/merge request, fetched from `git_ref` into the local `branch`.
+    /// `git_ref` is `None` on forges that don't publish request refs.
+    PullRequest {
+        number: u64,
+        git_ref: Option<String>,
+        branch: String,
+    },
+}
+
+impl WebLink {
+    fn new(repo: &[String], target: WebLinkTarget) -> WebLink {
+        WebLink {
+            repo: repo.to_vec(),
+            target,
+        }
+    }
+}
+
+/// Host-specific knowledge about a forge. `repo_path` arguments are the
+/// `/`-joined path below the host (e.g. `owner/repo` or `group/sub/project`).
+pub trait Forge: Sync {
+    /// List the repositories belonging to `owner` on `host`.
+    fn list_repos(&self, host: &str, owner: &str) -> Result<Vec<RemoteRepo>> {
+        let _ = owner;
+        bail!(
+            "listing repositories is not supported on {}; declare its forge in the [forges] config table",
+            host
+        )
+    }
+
+    /// Build the browser URL for a repository, branch, file or commit.
+    fn web_url(&self, host: &str, repo_path: &str, target: WebTarget) -> Option<String>;
+
+    /// The ref a pull/merge request is published under, if any: its head,
+    /// or on forges that only publish the merge result, that.
+    fn pull_request_ref(&self, number: u64) -> Option<String> {
+        let _ = number;
+        None
+    }
+
+    /// Local branch a pull/merge request is checked out as.
+    fn pull_request_branch(&self, number: u64) -> String {
+        format!("pr/{}", number)
+    }
+
+    /// The link target for pull/merge request `number`.
+    fn pull_request_target(&self, number: u64) -> WebLinkTarget {
+        WebLinkTarget::PullRequest {
+            number,
+            git_ref: self.pull_request_ref(number),
+            branch: self.pull_request_branch(number),
+        }
+    }
+
+    /// Recognise the path of a browser URL (with its `#` fragment) pointing
+    /// into a repository, e.g. a branch, file or commit page.
+    fn parse_web_path(&self, segments: &[String], fragment: Option<&str>) -> Option<WebLink> {
+        let _ = (segments, fragment);
+        None
+    }
+
+    /// Directory to use for the host in the local layout instead of the host
+    /// name, for forges reachable under several host names.
+    fn layout_host(&self, full_host: bool) -> Option<&'static str> {
+        let _ = full_host;
+        None
+    }
+
+    /// Reduce the path segments of a URL on `host` to the ones identifying the
+    /// repository, dropping transport-specific parts of the path.
+    fn layout_segments(&self, host: &str, segments: &[String]) -> Vec<String> {
+        let _ = host;
+        segments.to_vec()
+    }
+}
+
+struct GitHub;
+struct GitLab;
+struct Bitbucket;
+struct Gitea;
+struct AzureDevOps;
+struct Generic;
+
+/// Return the implementation for a forge kind.
+pub fn forge(kind: ForgeKind) -> &'static dyn Forge {
+    match kind {
+        ForgeKind::GitHub => &GitHub,
+        ForgeKind::GitLab => &GitLab,
+        ForgeKind::Bitbucket => &Bitbucket,
+        ForgeKind::Gitea => &Gitea,
+        ForgeKind::AzureDevOps => &AzureDevOps,
+        ForgeKind::Generic => &Generic,
+    }
+}
+
+/// Maps hosts to forge implementations: well-known public hosts plus any
+/// self-hosted instances declared in the `[forges]` config table.
+#[derive(Debug, Clone)]
+pub struct ForgeRegistry {
+    hosts: BTreeMap<String, ForgeKind>,
+}
+
+impl Default for ForgeRegistry {
+    fn default() -> Self {
+        let mut registry = ForgeRegistry {
+            hosts: BTreeMap::new(),
+        };
+        for (host, kind) in [
+            ("github.com", ForgeKind::GitHub),
+            ("gitlab.com", ForgeKind::GitLab),
+            ("bitbucket.org", ForgeKind::Bitbucket),
+            ("gitea.com", ForgeKind::Gitea),
+            ("codeberg.org", ForgeKind::Gitea),
+            ("dev.azure.com", ForgeKind::AzureDevOps),
+            ("ssh.dev.azure.com", ForgeKind::AzureDevOps),
+            ("vs-ssh.visualstudio.com", ForgeKind::AzureDevOps),
+        ] {
+            registry.register(host, kind);
+        }
+        registry
+    }
+}
+
+impl ForgeRegistry {
+    /// Build a registry from the built-in hosts and the user's config.
+    pub fn from_config(config: &Config) -> Self {
+        let mut registry = ForgeRegistry::default();
+        for (host, kind) in &config.forges {
+            registry.register(host, *kind);
+        }
+        registry
+    }
+
+    fn register(&mut self, host: &str, kind: ForgeKind) {
+        self.hosts.insert(host.to_ascii_lowercase(), kind);
+    }
+
+    /// Return the forge kind for a host, falling back to `Generic`.
+    pub fn kind_for_host(&self, host: &str) -> ForgeKind {
+        let host = host.to_ascii_lowercase();
+        if let Some(kind) = self.hosts.get(&host) {
+            return *kind;
+        }
+        // Legacy Azure DevOps organisations live under `<org>.visualstudio.com`
+        if host.ends_with(".visualstudio.com") {
+            return ForgeKind::AzureDevOps;
+        }
+        ForgeKind::Generic
+    }
+
+    pub fn forge_for_host(&self, host: &str) -> &'static dyn Forge {
+        forge(self.kind_for_host(host))
+    }
+
+    /// Recognise a browser URL (e.g. `https://github.com/o/r/blob/main/x.rs#L4`)
+    /// or a repository URL with a `#<number>` request suffix, and return the
+    /// link together with the repository's https clone URL.
+    pub fn web_link(&self, spec: &RepoSpec) -> Option<(WebLink, String)> {
+        if !matches!(spec.scheme, Scheme::Https | Scheme::Http | Scheme::Plain) {
+            return None;
+        }
+        let forge = self.forge_for_host(&spec.host);
+        let fragment = spec.fragment.as_deref();
+        // `owner/repo#123` names a pull/merge request of the repository
+        let link = match fragment.and_then(|f| f.parse::<u64>().ok()) {
+            Some(number) if !spec.segments.is_empty() => {
+                WebLink::new(&spec.segments, forge.pull_request_target(number))
+            }
+            _ => forge.parse_web_path(&spec.segments, fragment)?,
+        };
+        let scheme = if spec.scheme == Scheme::Http {
+            "http"
+        } else {
+            "https"
+        };
+        let mut url = Url::parse(&format!("{}://{}/", scheme, spec.host)).ok()?;
+        url.set_port(spec.port).ok()?;
+        url.path_segments_mut().ok()?.extend(&link.repo);
+        Some((link, format!("{}.git", url)))
+    }
+
+    /// The browser URL of a page in the repository a remote URL (https, ssh,
+    /// scp-style, ...) points at. ssh aliases are resolved through `ssh`.
+    /// `None` for local sources and forges without a page for `target`.
+    pub fn web_url(&self, spec: &RepoSpec, ssh: &SshConfig, target: WebTarget) -> Option<String> {
+        if spec.is_local() || spec.segments.is_empty() {
+            return None;
+        }
+        let host = match spec.scheme {
+            Scheme::Ssh | Scheme::Scp => ssh.hostname(&spec.host),
+            _ => None,
+        }
+        .unwrap_or_else(|| spec.host.clone());
+        let forge = self.forge_for_host(&host);
+        let repo_path = forge.layout_segments(&host, &spec.segments).join("/");
+        // An ssh port says nothing about where the web UI is served
+        let host = match (spec.scheme, spec.port) {
+            (Scheme::Https | Scheme::Http | Scheme::Plain, Some(port)) => {
+                format!("{}:{}", host, port)
+            }
+            _ => host,
+        };
+        forge.web_url(&host, &repo_path, target)
+    }
+}
+
+/// Split off the first `n` segments, requiring something after them.
+fn split_at_checked(segments: &[String], n: usize) -> Option<(&[String], &[String])> {
+    (segments.len() > n).then(|| segments.split_at(n))
+}
+
+/// Build a link from the `tree`/`blob`/`commit` routes GitHub and GitLab
+/// share, plus their `pull/N` and `merge_requests/N` pages.
+fn web_link_from_route(
+    forge: &dyn Forge,
+    repo: &[String],
+    route: &[String],
+    fragment: Option<&str>,
+) -> Option<WebLink> {
+    if repo.is_empty() {
+        return None;
+    }
+    let (kind, rest) = route.split_first()?;
+    let target = match kind.as_str() {
+        "tree" | "blob" if !rest.is_empty() => WebLinkTarget::Ref {
+            rest: rest.to_vec(),
+            line: parse_line_fragment(fragment),
+        },
+        "commit" => WebLinkTarget::Commit(rest.first()?.clone()),
+        "pull" | "merge_requests" => forge.pull_request_target(rest.first()?.parse().ok()?),
+        _ => return None,
+    };
+    Some(WebLink::new(repo, target))
+}
+
+/// The first line of `#L42`, `#L42-L50`, `#L42C7` or Bitbucket's `#lines-42:50`.
+fn parse_line_fragment(fragment: Option<&str>) -> Option<u32> {
+    let fragment = fragment?;
+    let digits = fragment
+        .strip_prefix("lines-")
+        .or_else(|| fragment.strip_prefix('L'))?;
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    digits[..end].parse().ok()
+}
+
+/// The `L<line>` anchor used by GitHub, GitLab and Gitea.
+fn line_anchor(line: Option<u32>) -> Option<String> {
+    line.map(|l| format!("L{}", l))
+}
+
+/// `https://<host>/<repo_path>/<route>`, with every segment percent-encoded.
+/// Route parts may contain `/` (branch names, file paths), which separates
+/// segments.
+fn page_url(host: &str, repo_path: &str, route: &[&str]) -> Option<Url> {
+    let mut url = Url::parse(&format!("https://{}/", host)).ok()?;
+    url.path_segments_mut()
+        .ok()?
+        .pop_if_empty()
+        .extend(repo_path.split('/'))
+        .extend(route.iter().flat_map(|part| part.split('/')));
+    Some(url)
+}
+
+/// [`page_url`] with a `#` anchor.
+fn anchored(mut url: Url, anchor: Option<String>) -> Url {
+    url.set_fragment(anchor.as_deref());
+    url
+}
+
+fn str_field(v: &Value, key: &str) -> Option<String> {
+    v.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+/// A non-success HTTP status, kept typed so callers can tell a missing
+/// resource from other failures.
+#[derive(Debug, thiserror::Error)]
+#[error("request to {url} failed with HTTP {status}")]
+struct HttpError {
+    url: String,
+    status: u16,
+}
+
+/// Whether `err` is a 404 response.
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<HttpError>()
+        .is_some_and(|e| e.status == 404)
+}
+
+/// A decoded JSON response and the URL of the next page, if any.
+struct Page {
+    body: Value,
+    next: Option<String>,
+}
+
+/// Perform a GET request through `curl` and decode the JSON body, along with
+/// where the next page is: the `Link: <...>; rel="next"` header, or
+/// Bitbucket's `next` field. A bearer or custom auth header is added when
+/// `auth` is set.
+fn http_get(url: &str, auth: Option<(&str, String)>) -> Result<Page> {
+    let curl = which("curl").context("curl executable not found in PATH")?;
+    let mut cmd = Command::new(curl);
+    cmd.arg("-sSLi")
+        .arg("-H")
+        .arg("Accept: application/json")
+        .arg("-H")
+        .arg("User-Agent: git-mirror");
+    if let Some((header, value)) = auth {
+        cmd.arg("-H").arg(format!("{}: {}", header, value));
+    }
+    let output = cmd.arg(url).output().context("failed to spawn curl")?;
+    if !output.status.success() {
+        bail!(
+            "request to {} failed: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let (status, headers, body) = split_response(&output.stdout)
+        .ok_or_else(|| anyhow!("malformed HTTP response from {}", url))?;
+    if !(200..300).contains(&status) {
+        return Err(HttpError {
+            url: url.to_string(),
+            status,
+        }
+        .into());
+    }
+    let body: Value =
+        serde_json::from_slice(body).with_context(|| format!("invalid JSON from {}", url))?;
+    let next = next_link(headers).or_else(|| str_field(&body, "next"));
+    Ok(Page { body, next })
+}
+
+/// Split `curl -i` output into the final status code, its headers and the
+/// body, skipping the header blocks of redirects and proxy responses.
+fn split_response(raw: &[u8]) -> Option<(u16, &str, &[u8])> {
+    let mut rest = raw;
+    let mut last = None;
+    while rest.starts_with(b"HTTP/") {
+        let end = rest.windows(4).position(|w| w == b"\r\n\r\n")?;
+        last = Some(std::str::from_utf8(&rest[..end]).ok()?);
+        rest = &rest[end + 4..];
+    }
+    let headers = last?;
+    let status = headers.split_whitespace().nth(1)?.parse().ok()?;
+    Some((status, headers, rest))
+}
+
+/// The `rel="next"` URL of a `Link` header.
+fn next_link(headers: &str) -> Option<String> {
+    let value = headers.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("link").then_some(value)
+    })?;
+    value.split(',').find_map(|part| {
+        let (target, params) = part.split_once(';')?;
+        params
+            .split(';')
+            .any(|p| p.trim() == "rel=\"next\"")
+            .then(|| {
+                target
+                    .trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+    })
+}
+
+/// Collect the repositories on every page starting at `url`. `items` picks
+/// the repository array out of a page.
+fn list_all_pages(
+    url: String,
+    auth: Option<(&str, String)>,
+    items: fn(&Value) -> Option<&Vec<Value>>,
+    parse: fn(&Value) -> Option<RemoteRepo>,
+) -> Result<Vec<RemoteRepo>> {
+    let mut repos = Vec::new();
+    let mut next = Some(url);
+    while let Some(url) = next.take() {
+        let page = http_get(&url, auth.clone())?;
+        repos.extend(items(&page.body).into_iter().flatten().filter_map(parse));
+        next = page.next.filter(|n| *n != url);
+    }
+    Ok(repos)
+}
+
+fn env_token(var: &str) -> Option<String> {
+    std::env::var(var).ok().filter(|t| !t.is_empty())
+}
+
+fn split_owner_repo(repo_path: &str) -> Result<(&str, &str)> {
+    repo_path
+        .rsplit_once('/')
+        .ok_or_else(|| anyhow!("expected owner/repo, got {}", repo_path))
+}
+
+impl GitHub {
+    fn api_base(host: &str) -> String {
+        if host.eq_ignore_ascii_case("github.com") {
+            "https://api.github.com".to_string()
+        } else {
+            // GitHub Enterprise Server
+            format!("https://{}/api/v3", host)
+        }
+    }
+
+    fn auth() -> Option<(&'static str, String)> {
+        env_token("GITHUB_TOKEN").map(|t| ("Authorization", format!("Bearer {}", t)))
+    }
+
+    fn parse_repo(v: &Value) -> Option<RemoteRepo> {
+        Some(RemoteRepo {
+            path: str_field(v, "full_name")?,
+            clone_url: str_field(v, "clone_url")?,
+            description: str_field(v, "description"),
+            archived: v.get("archived").and_then(Value::as_bool).unwrap_or(false),
+        })
+    }
+}
+
+impl Forge for GitHub {
+    fn list_repos(&self, host: &str, owner: &str) -> Result<Vec<RemoteRepo>> {
+        let url = format!(
+            "{}/users/{}/repos?per_page=100",
+            Self::api_base(host),
+            owner
+        );
+        list_all_pages(url, Self::auth(), Value::as_array, Self::parse_repo)
+    }
+
+    /// `owner/repo/tree/<ref>[/<dir>]`, `owner/repo/blob/<ref>/<file>#L42`,
+    /// `owner/repo/commit/<sha>` and `owner/repo/pull/<n>`.
+    fn parse_web_path(&self, segments: &[String], fragment: Option<&str>) -> Option<WebLink> {
+        let (repo, route) = split_at_checked(segments, 2)?;
+        web_link_from_route(self, repo, route, fragment)
+    }
+
+    fn web_url(&self, host: &str, repo_path: &str, target: WebTarget) -> Option<String> {
+        let url = match target {
+            WebTarget::Repo => page_url(host, repo_path, &[])?,
+            WebTarget::Branch(b) => page_url(host, repo_path, &["tree", b])?,
+            WebTarget::File { rev, path, line } => anchored(
+                page_url(host, repo_path, &["blob", rev.name(), path])?,
+                line_anchor(line),
+            ),
+            WebTarget::Commit(sha) => page_url(host, repo_path, &["commit", sha])?,
+        };
+        Some(url.into())
+    }
+
+    fn pull_request_ref(&self, number: u64) -> Option<String> {
+        Some(format!("refs/pull/{}/head", number))
+    }
+}
+
+impl GitLab {
+    fn auth() -> Option<(&'static str, String)> {
+        env_token("GITLAB_TOKEN").map(|t| ("PRIVATE-TOKEN", t))
+    }
+
+    fn parse_repo(v: &Value) -> Option<RemoteRepo> {
+        Some(RemoteRepo {
+            path: str_field(v, "path_with_namespace")?,
+            clone_url: str_field(v, "http_url_to_repo")?,
+            description: str_field(v, "description"),
+            archived: v.get("archived").and_then(Value::as_bool).unwrap_or(false),
+        })
+    }
+}
+
+impl Forge for GitLab {
+    fn list_repos(&self, host: &str, owner: &str) -> Result<Vec<RemoteRepo>> {
+        // Owners are either groups or users; try the group endpoint first
+        let encoded = owner.replace('/', "%2F");
+        let group = format!(
+            "https://{}/api/v4/groups/{}/projects?per_page=100",
+            host, encoded
+        );
+        match list_all_pages(group, Self::auth(), Value::as_array, Self::parse_repo) {
+            Err(e) if is_not_found(&e) => {
+                let user = format!(
+                    "https://{}/api/v4/users/{}/projects?per_page=100",
+                    host, encoded
+                );
+                list_all_pages(user, Self::auth(), Value::as_array, Self::parse_repo)
+            }
+            result => result,
+        }
+    }
+
+    /// Web routes sit behind a `-` segment, since groups nest arbitrarily:
+    /// `group/sub/project/-/tree/<ref>`, `.../-/blob/<ref>/<file>#L42`,
+    /// `.../-/commit/<sha>`, `.../-/merge_requests/<n>`.
+    fn parse_web_path(&self, segments: &[String], fragment: Option<&str>) -> Option<WebLink> {
+        let dash = segments.iter().position(|s| s == "-")?;
+        web_link_from_route(self, &segments[..dash], &segments[dash + 1..], fragment)
+    }
+
+    fn web_url(&self, host: &str, repo_path: &str, target: WebTarget) -> Option<String> {
+        let url = match target {
+            WebTarget::Repo => page_url(host, repo_path, &[])?,
+            WebTarget::Branch(b) => page_url(host, repo_path, &["-", "tree", b])?,
+            WebTarget::File { rev, path, line } => anchored(
+                page_url(host, repo_path, &["-", "blob", rev.name(), path])?,
+                line_anchor(line),
+            ),
+            WebTarget::Commit(sha) => page_url(host, repo_path, &["-", "commit", sha])?,
+        };
+        Some(url.into())
+    }
+
+    fn pull_request_ref(&self, number: u64) -> Option<String> {
+        Some(format!("refs/merge-requests/{}/head", number))
+    }
+
+    fn pull_request_branch(&self, number: u64) -> String {
+        format!("mr/{}", number)
+    }
+}
+
+impl Bitbucket {
+    fn parse_repo(v: &Value) -> Option<RemoteRepo> {
+        let clone_links = v.get("links")?.get("clone")?.as_array()?;
+        let link = |name: &str| {
+            clone_links
+                .iter()
+                .find(|l| l.get("name").and_then(Value::as_str) == Some(name))
+                .and_then(|l| str_field(l, "href"))
+        };
+        Some(RemoteRepo {
+            path: str_field(v, "full_name")?,
+            clone_url: link("https")?,
+            description: str_field(v, "description").filter(|d| !d.is_empty()),
+            archived: false,
+        })
+    }
+}
+
+impl Forge for Bitbucket {
+    /// Bitbucket Data Center has a different API, so only bitbucket.org is
+    /// supported.
+    fn list_repos(&self, host: &str, owner: &str) -> Result<Vec<RemoteRepo>> {
+        if !host.eq_ignore_ascii_case("bitbucket.org") {
+            bail!(
+                "listing repositories is only supported on bitbucket.org, not {}",
+                host
+            );
+        }
+        let url = format!(
+            "https://api.bitbucket.org/2.0/repositories/{}?pagelen=100",
+            owner
+        );
+        list_all_pages(
+            url,
+            None,
+            |page| page.get("values")?.as_array(),
+            Self::parse_repo,
+        )
+    }
+
+    /// `owner/repo/src/<ref>/<path>#lines-42`, `owner/repo/branch/<ref>` and
+    /// `owner/repo/commits/<sha>`. Pull requests aren't published under a ref,
+    /// so their pages aren't recognised.
+    fn parse_web_path(&self, segments: &[String], fragment: Option<&str>) -> Option<WebLink> {
+        let (repo, route) = split_at_checked(segments, 2)?;
+        let (kind, rest) = route.split_first()?;
+        let target = match kind.as_str() {
+            "src" | "branch" if !rest.is_empty() => WebLinkTarget::Ref {
+                rest: rest.to_vec(),
+                line: parse_line_fragment(fragment),
+            },
+            "commits" => WebLinkTarget::Commit(rest.first()?.clone()),
+            _ => return None,
+        };
+        Some(WebLink::new(repo, target))
+    }
+
+    /// Branch and commit pages share the `src/<rev>` route.
+    fn web_url(&self, host: &str, repo_path: &str, target: WebTarget) -> Option<String> {
+        let url = match target {
+            WebTarget::Repo => page_url(host, repo_path, &[])?,
+            WebTarget::Branch(b) => page_url(host, repo_path, &["src", b])?,
+            WebTarget::File { rev, path, line } => anchored(
+                page_url(host, repo_path, &["src", rev.name(), path])?,
+                line.map(|l| format!("lines-{}", l)),
+            ),
+            WebTarget::Commit(sha) => page_url(host, repo_path, &["commits", sha])?,
+        };
+        Some(url.into())
+    }
+}
+
+impl Gitea {
+    fn auth() -> Option<(&'static str, String)> {
+        env_token("GITEA_TOKEN").map(|t| ("Authorization", format!("token {}", t)))
+    }
+
+    fn parse_repo(v: &Value) -> Option<RemoteRepo> {
+        Some(RemoteRepo {
+            path: str_field(v, "full_name")?,
+            clone_url: str_field(v, "clone_url")?,
+            description: str_field(v, "description").filter(|d| !d.is_empty()),
+            archived: v.get("archived").and_then(Value::as_bool).unwrap_or(false),
+        })
+    }
+}
+
+impl Forge for Gitea {
+    fn list_repos(&self, host: &str, owner: &str) -> Result<Vec<RemoteRepo>> {
+        // Organisations and users have separate endpoints
+        let org = format!("https://{}/api/v1/orgs/{}/repos?limit=50", host, owner);
+        match list_all_pages(org, Self::auth(), Value::as_array, Self::parse_repo) {
+            Err(e) if is_not_found(&e) => {
+                let user = format!("https://{}/api/v1/users/{}/repos?limit=50", host, owner);
+                list_all_pages(user, Self::auth(), Value::as_array, Self::parse_repo)
+            }
+            result => result,
+        }
+    }
+
+    /// `owner/repo/src/branch/<ref>/<path>#L42` (also `src/tag/` and
+    /// `src/commit/`), `owner/repo/commit/<sha>` and `owner/repo/pulls/<n>`.
+    fn parse_web_path(&self, segments: &[String], fragment: Option<&str>) -> Option<WebLink> {
+        let (repo, route) = split_at_checked(segments, 2)?;
+        let target = match route {
+            [src, kind, rest @ ..] if src == "src" && !rest.is_empty() => match kind.as_str() {
+                "branch" | "tag" | "commit" => WebLinkTarget::Ref {
+                    rest: rest.to_vec(),
+                    line: parse_line_fragment(fragment),
+                },
+                _ => return None,
+            },
+            [commit, sha, ..] if commit == "commit" => WebLinkTarget::Commit(sha.clone()),
+            [pulls, n, ..] if pulls == "pulls" => self.pull_request_target(n.parse().ok()?),
+            _ => return None,
+        };
+        Some(WebLink::new(repo, target))
+    }
+
+    fn web_url(&self, host: &str, repo_path: &str, target: WebTarget) -> Option<String> {
+        let url = match target {
+            WebTarget::Repo => page_url(host, repo_path, &[])?,
+            WebTarget::Branch(b) => page_url(host, repo_path, &["src", "branch", b])?,
+            WebTarget::File { rev, path, line } => {
+                let kind = match rev {
+                    WebRev::Branch(_) => "branch",
+                    WebRev::Commit(_) => "commit",
+                };
+                anchored(
+                    page_url(host, repo_path, &["src", kind, rev.name(), path])?,
+                    line_anchor(line),
+                )
+            }
+            WebTarget::Commit(sha) => page_url(host, repo_path, &["commit", sha])?,
+        };
+        Some(url.into())
+    }
+
+    fn pull_request_ref(&self, number: u64) -> Option<String> {
+        Some(format!("refs/pull/{}/head", number))
+    }
+}
+
+impl AzureDevOps {
+    /// The host serving the web UI and API: dev.azure.com for every cloud
+    /// host name, the host itself for Azure DevOps Server.
+    fn service_host(host: &str) -> &str {
+        let lower = host.to_ascii_lowercase();
+        if lower == "dev.azure.com"
+            || lower == "ssh.dev.azure.com"
+            || lower.ends_with(".visualstudio.com")
+        {
+            "dev.azure.com"
+        } else {
+            host
+        }
+    }
+
+    fn auth() -> Option<(&'static str, String)> {
+        env_token("AZURE_DEVOPS_TOKEN").map(|t| ("Authorization", format!("Bearer {}", t)))
+    }
+
+    fn parse_repo(v: &Value) -> Option<RemoteRepo> {
+        let project = v.get("project").and_then(|p| str_field(p, "name"))?;
+        let name = str_field(v, "name")?;
+        let remote_url = str_field(v, "remoteUrl")?;
+        // remoteUrl is https://<org>@dev.azure.com/<org>/<project>/_git/<repo>,
+        // or https://<server>/<collection>/<project>/_git/<repo>
+        let org = Url::parse(&remote_url)
+            .ok()?
+            .path_segments()?
+            .next()?
+            .to_string();
+        Some(RemoteRepo {
+            path: format!("{}/{}/{}", org, project, name),
+            clone_url: remote_url,
+            description: None,
+            archived: v
+                .get("isDisabled")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        })
+    }
+}
+
+impl Forge for AzureDevOps {
+    /// `owner` is an organisation (a collection on Azure DevOps Server),
+    /// optionally followed by `/<project>`.
+    fn list_repos(&self, host: &str, owner: &str) -> Result<Vec<RemoteRepo>> {
+        let url = format!(
+            "https://{}/{}/_apis/git/repositories?api-version=7.0",
+            Self::service_host(host),
+            owner
+        );
+        let body = http_get(&url, Self::auth())?.body;
+        Ok(body
+            .get("value")
+            .and_then(Value::as_array)
+            .map(|a| a.iter().filter_map(Self::parse_repo).collect())
+            .unwrap_or_default())
+    }
+
+    /// `repo_path` is `org/project/repo`; the web UI of every cloud host name
+    /// lives on dev.azure.com.
+    fn web_url(&self, host: &str, repo_path: &str, target: WebTarget) -> Option<String> {
+        let (org_project, repo) = split_owner_repo(repo_path).ok()?;
+        let mut url = page_url(Self::service_host(host), org_project, &["_git", repo])?;
+        let version = |rev: WebRev| match rev {
+            WebRev::Branch(b) => format!("GB{}", b),
+            WebRev::Commit(sha) => format!("GC{}", sha),
+        };
+        match target {
+            WebTarget::Repo => {}
+            WebTarget::Branch(b) => {
+                url.query_pairs_mut()
+                    .append_pair("version", &version(WebRev::Branch(b)));
+            }
+            WebTarget::File { rev, path, line } => {
+                let mut query = url.query_pairs_mut();
+                query
+                    .append_pair("path", &format!("/{}", path))
+                    .append_pair("version", &version(rev));
+                if let Some(line) = line {
+                    let line = line.to_string();
+                    query
+                        .append_pair("line", &line)
+                        .append_pair("lineEnd", &line)
+                        .append_pair("lineStartColumn", "1");
+                }
+            }
+            WebTarget::Commit(sha) => {
+                url.path_segments_mut().ok()?.extend(["commit", sha]);
+            }
+        }
+        Some(url.into())
+    }
+
+    /// Azure DevOps doesn't publish pull request heads, only the result of
+    /// merging them into the target branch, so that is what gets checked out.
+    fn pull_request_ref(&self, number: u64) -> Option<String> {
+        Some(format!("refs/pull/{}/merge", number))
+    }
+
+    /// https, ssh and legacy visualstudio.com URLs all name the same
+    /// repositories, so they share one directory.
+    fn layout_host(&self, full_host: bool) -> Option<&'static str> {
+        Some(if full_host { "dev.azure.com" } else { "azure" })
+    }
+
+    /// Map every URL form to `org/project/repo`:
+    /// - `dev.azure.com/org/project/_git/repo`
+    /// - `ssh.dev.azure.com:v3/org/project/repo` (and `vs-ssh.visualstudio.com`)
+    /// - `org.visualstudio.com/[DefaultCollection/]project/_git/repo`
+    ///
+    /// A missing project (`org/_git/repo`) means the project named after the repo.
+    fn layout_segments(&self, host: &str, segments: &[String]) -> Vec<String> {
+        let host = host.to_ascii_lowercase();
+        let segs: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let mapped = match segs.as_slice() {
+            ["v3", org, project, repo] => Some([*org, *project, *repo]),
+            _ if host == "dev.azure.com" => match segs.as_slice() {
+                [org, project, "_git", repo] => Some([*org, *project, *repo]),
+                [org, "_git", repo] => Some([*org, *repo, *repo]),
+                _ => None,
+            },
+            _ => host.strip_suffix(".visualstudio.com").and_then(|org| {
+                let segs = match segs.as_slice() {
+                    ["DefaultCollection", rest @ ..] => rest,
+                    rest => rest,
+                };
+                match segs {
+                    [project, "_git", repo] => Some([org, *project, *repo]),
+                    ["_git", repo] => Some([org, *repo, *repo]),
+                    _ => None,
+                }
+            }),
+        };
+        match mapped {
+            Some(parts) => parts.iter().map(|p| p.to_string()).collect(),
+            None => segments.to_vec(),
+        }
+    }
+}
+
+impl Forge for Generic {
+    /// Unknown software: only the repository page itself can be guessed.
+    fn web_url(&self, host: &str, repo_path: &str, target: WebTarget) -> Option<String> {
+        match target {
+            WebTarget::Repo => page_url(host, repo_path, &[]).map(String::from),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        forge, is_not_found, next_link, parse_line_fragment, split_response, AzureDevOps,
+        Bitbucket, ForgeKind, ForgeRegistry, GitHub, HttpError, WebLink, WebLinkTarget, WebRev,
+        WebTarget,
+    };
+    use crate::config::Config;
+    use crate::spec::RepoSpec;
+    use crate::ssh_config::SshConfig;
+    use serde_json::json;
+
+    #[test]
+    fn test_registry_builtin_hosts() {
+        let r = ForgeRegistry::default();
+        assert_eq!(r.kind_for_host("github.com"), ForgeKind::GitHub);
+        assert_eq!(r.kind_for_host("GitLab.com"), ForgeKind::GitLab);
+        assert_eq!(r.kind_for_host("bitbucket.org"), ForgeKind::Bitbucket);
+        assert_eq!(r.kind_for_host("codeberg.org"), ForgeKind::Gitea);
+        assert_eq!(r.kind_for_host("dev.azure.com"), ForgeKind::AzureDevOps);
+        assert_eq!(
+            r.kind_for_host("contoso.visualstudio.com"),
+            ForgeKind::AzureDevOps
+        );
+        assert_eq!(r.kind_for_host("git.example.com"), ForgeKind::Generic);
+    }
+
+    #[test]
+    fn test_registry_from_config() {
+        let mut config = Config::default();
+        config
+            .forges
+            .insert("Git.Corp.Example".to_string(), ForgeKind::GitLab);
+        let r = ForgeRegistry::from_config(&config);
+        assert_eq!(r.kind_for_host("git.corp.example"), ForgeKind::GitLab);
+        assert_eq!(
+            r.forge_for_host("git.corp.example")
+                .pull_request_ref(1)
+                .as_deref(),
+            Some("refs/merge-requests/1/head")
+        );
+        assert_eq!(r.kind_for_host("github.com"), ForgeKind::GitHub);
+    }
+
+    #[test]
+    fn test_web_urls_github() {
+        let f = forge(ForgeKind::GitHub);
+        assert_eq!(
+            f.web_url("github.com", "o/r", WebTarget::Repo).as_deref(),
+            Some("https://github.com/o/r")
+        );
+        assert_eq!(
+            f.web_url("github.com", "o/r", WebTarget::Branch("feature/x"))
+                .as_deref(),
+            Some("https://github.com/o/r/tree/feature/x")
+        );
+        assert_eq!(
+            f.web_url(
+                "github.com",
+                "o/r",
+                WebTarget::File {
+                    rev: WebRev::Branch("main"),
+                    path: "src/lib.rs",
+                    line: Some(42)
+                }
+            )
+            .as_deref(),
+            Some("https://github.com/o/r/blob/main/src/lib.rs#L42")
+        );
+        assert_eq!(
+            f.web_url("github.com", "o/r", WebTarget::Commit("abc123"))
+                .as_deref(),
+            Some("https://github.com/o/r/commit/abc123")
+        );
+    }
+
+    #[test]
+    fn test_web_urls_encode_segments() {
+        let file = WebTarget::File {
+            rev: WebRev::Branch("fix/#12 wip"),
+            path: "docs/a b?.md",
+            line: Some(4),
+        };
+        assert_eq!(
+            forge(ForgeKind::GitHub)
+                .web_url("github.com", "o/my repo", file)
+                .as_deref(),
+            Some("https://github.com/o/my%20repo/blob/fix/%2312%20wip/docs/a%20b%3F.md#L4")
+        );
+        assert_eq!(
+            forge(ForgeKind::GitLab)
+                .web_url("gitlab.com", "g/my repo", WebTarget::Branch("a#b"))
+                .as_deref(),
+            Some("https://gitlab.com/g/my%20repo/-/tree/a%23b")
+        );
+        assert_eq!(
+            forge(ForgeKind::AzureDevOps)
+                .web_url("dev.azure.com", "org/my proj/repo", file)
+                .as_deref(),
+            Some(
+                "https://dev.azure.com/org/my%20proj/_git/repo\
+                 ?path=%2Fdocs%2Fa+b%3F.md&version=GBfix%2F%2312+wip\
+                 &line=4&lineEnd=4&lineStartColumn=1"
+            )
+        );
+    }
+
+    #[test]
+    fn test_web_urls_gitlab_bitbucket_gitea() {
+        let file = WebTarget::File {
+            rev: WebRev::Branch("main"),
+            path: "README.md",
+            line: Some(3),
+        };
+        assert_eq!(
+            forge(ForgeKind::GitLab)
+                .web_url("gitlab.com", "g/sub/p", file)
+                .as_deref(),
+            Some("https://gitlab.com/g/sub/p/-/blob/main/README.md#L3")
+        );
+        assert_eq!(
+            forge(ForgeKind::Bitbucket)
+                .web_url("bitbucket.org", "o/r", file)
+                .as_deref(),
+            Some("https://bitbucket.org/o/r/src/main/README.md#lines-3")
+        );
+        assert_eq!(
+            forge(ForgeKind::Gitea)
+                .web_url("codeberg.org", "o/r", file)
+                .as_deref(),
+            Some("https://codeberg.org/o/r/src/branch/main/README.md#L3")
+        );
+        assert_eq!(
+            forge(ForgeKind::Gitea)
+                .web_url(
+                    "codeberg.org",
+                    "o/r",
+                    WebTarget::File {
+                        rev: WebRev::Commit("abc123"),
+                        path: "README.md",
+                        line: None
+                    }
+                )
+                .as_deref(),
+            Some("https://codeberg.org/o/r/src/commit/abc123/README.md")
+        );
+    }
+
+    #[test]
+    fn test_web_urls_azure_and_generic() {
+        let az = forge(ForgeKind::AzureDevOps);
+        assert_eq!(
+            az.web_url("ssh.dev.azure.com", "org/proj/repo", WebTarget::Repo)
+                .as_deref(),
+            Some("https://dev.azure.com/org/proj/_git/repo")
+        );
+        assert_eq!(
+            az.web_url("dev.azure.com", "org/proj/repo", WebTarget::Branch("dev"))
+                .as_deref(),
+            Some("https://dev.azure.com/org/proj/_git/repo?version=GBdev")
+        );
+        // A detached HEAD pins files to the commit
+        assert_eq!(
+            az.web_url(
+                "dev.azure.com",
+                "org/proj/repo",
+                WebTarget::File {
+                    rev: WebRev::Commit("abc123"),
+                    path: "a.rs",
+                    line: None
+                }
+            )
+            .as_deref(),
+            Some("https://dev.azure.com/org/proj/_git/repo?path=%2Fa.rs&version=GCabc123")
+        );
+        assert_eq!(
+            az.web_url(
+                "dev.azure.com",
+                "org/proj/repo",
+                WebTarget::Commit("abc123")
+            )
+            .as_deref(),
+            Some("https://dev.azure.com/org/proj/_git/repo/commit/abc123")
+        );
+        // Azure DevOps Server serves its own web UI
+        assert_eq!(
+            az.web_url("tfs.example.com", "Main/proj/repo", WebTarget::Repo)
+                .as_deref(),
+            Some("https://tfs.example.com/Main/proj/_git/repo")
+        );
+        let generic = forge(ForgeKind::Generic);
+        assert_eq!(
+            generic
+                .web_url("git.example.com", "o/r", WebTarget::Repo)
+                .as_deref(),
+            Some("https://git.example.com/o/r")
+        );
+        assert!(generic
+            .web_url("git.example.com", "o/r", WebTarget::Branch("main"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_repo_web_urls() {
+        let registry = ForgeRegistry::default();
+        let ssh = SshConfig::default();
+        let url = |repo: &str, target| {
+            registry.web_url(&RepoSpec::parse(repo).expect("parse"), &ssh, target)
+        };
+        for repo in [
+            "git@github.com:owner/repo.git",
+            "ssh://git@github.com/owner/repo",
+            "https://github.com/owner/repo.git",
+            "git+ssh://git@github.com:22/owner/repo.git",
+            "github.com/owner/repo",
+        ] {
+            assert_eq!(
+                url(repo, WebTarget::Repo).as_deref(),
+                Some("https://github.com/owner/repo"),
+                "{}",
+                repo
+            );
+        }
+        assert_eq!(
+            url(
+                "git@gitlab.com:group/sub/project.git",
+                WebTarget::File {
+                    rev: WebRev::Branch("main"),
+                    path: "src/lib.rs",
+                    line: Some(7)
+                }
+            )
+            .as_deref(),
+            Some("https://gitlab.com/group/sub/project/-/blob/main/src/lib.rs#L7")
+        );
+        assert_eq!(
+            url(
+                "git@ssh.dev.azure.com:v3/org/proj/repo",
+                WebTarget::Commit("abc123")
+            )
+            .as_deref(),
+            Some("https://dev.azure.com/org/proj/_git/repo/commit/abc123")
+        );
+        // The ssh port is dropped, an https one kept
+        assert_eq!(
+            url("ssh://git@git.example.com:2222/o/r.git", WebTarget::Repo).as_deref(),
+            Some("https://git.example.com/o/r")
+        );
+        assert_eq!(
+            url("https://git.example.com:8443/o/r.git", WebTarget::Repo).as_deref(),
+            Some("https://git.example.com:8443/o/r")
+        );
+        assert_eq!(url("/srv/git/r.git", WebTarget::Repo), None);
+        assert_eq!(
+            url("git@git.example.com:o/r.git", WebTarget::Branch("main")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_repo_web_url_ssh_alias() {
+        let mut dir = std::env::temp_dir();
+        dir.push("git_mirror_forge_web_url_alias");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create dir");
+        std::fs::write(
+            dir.join("config"),
+            "Host github-work\n  HostName github.com\n",
+        )
+        .expect("write config");
+        let ssh = SshConfig::load_from(&dir.join("config"), &dir);
+        let spec = RepoSpec::parse("github-work:company/api.git").expect("parse");
+        assert_eq!(
+            ForgeRegistry::default()
+                .web_url(&spec, &ssh, WebTarget::Branch("dev"))
+                .as_deref(),
+            Some("https://github.com/company/api/tree/dev")
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_azure_layout() {
+        let azure = forge(ForgeKind::AzureDevOps);
+        assert_eq!(azure.layout_host(false), Some("azure"));
+        assert_eq!(azure.layout_host(true), Some("dev.azure.com"));
+        let cases = [
+            ("dev.azure.com", "org/project/_git/repo", "org/project/repo"),
+            ("dev.azure.com", "org/_git/repo", "org/repo/repo"),
+            (
+                "ssh.dev.azure.com",
+                "v3/org/project/repo",
+                "org/project/repo",
+            ),
+            (
+                "vs-ssh.visualstudio.com",
+                "v3/org/project/repo",
+                "org/project/repo",
+            ),
+            (
+                "org.visualstudio.com",
+                "project/_git/repo",
+                "org/project/repo",
+            ),
+            (
+                "org.visualstudio.com",
+                "DefaultCollection/project/_git/repo",
+                "org/project/repo",
+            ),
+            ("org.visualstudio.com", "_git/repo", "org/repo/repo"),
+            // Unrecognised shapes are left alone
+            ("dev.azure.com", "org/project", "org/project"),
+        ];
+        for (host, path, expected) in cases {
+            let segments: Vec<String> = path.split('/').map(str::to_string).collect();
+            assert_eq!(
+                azure.layout_segments(host, &segments).join("/"),
+                expected,
+                "{}/{}",
+                host,
+                path
+            );
+        }
+
+        let github = forge(ForgeKind::GitHub);
+        assert_eq!(github.layout_host(false), None);
+        let segments = vec!["o".to_string(), "_git".to_string(), "r".to_string()];
+        assert_eq!(github.layout_segments("github.com", &segments), segments);
+    }
+
+    fn web_link(url: &str) -> Option<(WebLink, String)> {
+        let spec = RepoSpec::parse(url).expect("parse");
+        ForgeRegistry::default().web_link(&spec)
+    }
+
+    fn ref_target(rest: &str, line: Option<u32>) -> WebLinkTarget {
+        WebLinkTarget::Ref {
+            rest: rest.split('/').map(str::to_string).collect(),
+            line,
+        }
+    }
+
+    #[test]
+    fn test_web_links() {
+        let cases = [
+            (
+                "https://github.com/o/r/tree/feature/x",
+                "o/r",
+                ref_target("feature/x", None),
+                "https://github.com/o/r.git",
+            ),
+            (
+                "https://github.com/o/r/blob/main/src/lib.rs#L42",
+                "o/r",
+                ref_target("main/src/lib.rs", Some(42)),
+                "https://github.com/o/r.git",
+            ),
+            (
+                "github.com/o/r/blob/main/src/lib.rs#L42-L50",
+                "o/r",
+                ref_target("main/src/lib.rs", Some(42)),
+                "https://github.com/o/r.git",
+            ),
+            (
+                "https://github.com/o/r/commit/abc123",
+                "o/r",
+                WebLinkTarget::Commit("abc123".to_string()),
+                "https://github.com/o/r.git",
+            ),
+            (
+                "https://gitlab.com/g/sub/p/-/tree/release/1.0",
+                "g/sub/p",
+                ref_target("release/1.0", None),
+                "https://gitlab.com/g/sub/p.git",
+            ),
+            (
+                "https://gitlab.com/g/p/-/blob/main/README.md#L7",
+                "g/p",
+                ref_target("main/README.md", Some(7)),
+                "https://gitlab.com/g/p.git",
+            ),
+            (
+                "https://bitbucket.org/o/r/src/main/a/b.py#lines-12",
+                "o/r",
+                ref_target("main/a/b.py", Some(12)),
+                "https://bitbucket.org/o/r.git",
+            ),
+            (
+                "https://codeberg.org/o/r/src/branch/dev/x/y.rs#L3",
+                "o/r",
+                ref_target("dev/x/y.rs", Some(3)),
+                "https://codeberg.org/o/r.git",
+            ),
+            (
+                "https://github.com/o/my%20repo/tree/main",
+                "o/my repo",
+                ref_target("main", None),
+                "https://github.com/o/my%20repo.git",
+            ),
+        ];
+        for (url, repo, target, clone_url) in cases {
+            let (link, got_clone) = web_link(url).unwrap_or_else(|| panic!("{}", url));
+            assert_eq!(link.repo.join("/"), repo, "{}", url);
+            assert_eq!(link.target, target, "{}", url);
+            assert_eq!(got_clone, clone_url, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_pull_request_links() {
+        let pr = |n: u64, git_ref: Option<&str>, branch: &str| WebLinkTarget::PullRequest {
+            number: n,
+            git_ref: git_ref.map(str::to_string),
+            branch: branch.to_string(),
+        };
+        let cases = [
+            (
+                "https://github.com/o/r/pull/123",
+                "o/r",
+                pr(123, Some("refs/pull/123/head"), "pr/123"),
+            ),
+            (
+                "https://github.com/o/r/pull/123/files",
+                "o/r",
+                pr(123, Some("refs/pull/123/head"), "pr/123"),
+            ),
+            (
+                "github.com/o/r#123",
+                "o/r",
+                pr(123, Some("refs/pull/123/head"), "pr/123"),
+            ),
+            (
+                "https://gitlab.com/g/p/-/merge_requests/45",
+                "g/p",
+                pr(45, Some("refs/merge-requests/45/head"), "mr/45"),
+            ),
+            (
+                "https://gitlab.com/g/sub/p#45",
+                "g/sub/p",
+                pr(45, Some("refs/merge-requests/45/head"), "mr/45"),
+            ),
+            (
+                "https://codeberg.org/o/r/pulls/7",
+                "o/r",
+                pr(7, Some("refs/pull/7/head"), "pr/7"),
+            ),
+        ];
+        for (url, repo, target) in cases {
+            let (link, _) = web_link(url).unwrap_or_else(|| panic!("{}", url));
+            assert_eq!(link.repo.join("/"), repo, "{}", url);
+            assert_eq!(link.target, target, "{}", url);
+        }
+        assert!(web_link("https://github.com/o/r/pull/abc").is_none());
+    }
+
+    #[test]
+    fn test_not_web_links() {
+        for url in [
+            "https://github.com/o/r",
+            "https://github.com/o/r.git",
+            "https://github.com/o/r/tree",
+            "https://github.com/o/r/issues/3",
+            "https://bitbucket.org/o/r/pull-requests/9",
+            "git@github.com:o/r/tree/main",
+            "https://gitlab.com/g/sub/p",
+            "https://git.example.com/o/r/tree/main",
+        ] {
+            assert!(web_link(url).is_none(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_split_response() {
+        let raw = concat!(
+            "HTTP/1.1 301 Moved\r\nLocation: /b\r\n\r\n",
+            "HTTP/2 200\r\n",
+            "link: <https://h/a?page=2>; rel=\"next\", <https://h/a?page=9>; rel=\"last\"\r\n\r\n",
+            "[1]",
+        );
+        let (status, headers, body) = split_response(raw.as_bytes()).expect("split");
+        assert_eq!(status, 200);
+        assert_eq!(body, b"[1]");
+        assert_eq!(next_link(headers).as_deref(), Some("https://h/a?page=2"));
+
+        let (status, headers, _) =
+            split_response(b"HTTP/1.1 404 Not Found\r\nLink: <https://h/a>; rel=\"prev\"\r\n\r\n")
+                .expect("split");
+        assert_eq!(status, 404);
+        assert_eq!(next_link(headers), None);
+        assert!(split_response(b"[]").is_none());
+    }
+
+    #[test]
+    fn test_is_not_found() {
+        let err = |status| {
+            anyhow::Error::from(HttpError {
+                url: "https://h/a".to_string(),
+                status,
+            })
+        };
+        assert!(is_not_found(&err(404)));
+        assert!(!is_not_found(&err(401)));
+        assert!(!is_not_found(&anyhow::anyhow!("connection refused")));
+    }
+
+    #[test]
+    fn test_parse_line_fragment() {
+        assert_eq!(parse_line_fragment(Some("L42")), Some(42));
+        assert_eq!(parse_line_fragment(Some("L42C7")), Some(42));
+        assert_eq!(parse_line_fragment(Some("lines-12:20")), Some(12));
+        assert_eq!(parse_line_fragment(Some("readme")), None);
+        assert_eq!(parse_line_fragment(None), None);
+    }
+
+    #[test]
+    fn test_pull_request_refs() {
+        assert_eq!(
+            forge(ForgeKind::GitHub).pull_request_ref(7).as_deref(),
+            Some("refs/pull/7/head")
+        );
+        assert_eq!(
+            forge(ForgeKind::GitLab).pull_request_ref(45).as_deref(),
+            Some("refs/merge-requests/45/head")
+        );
+        assert_eq!(
+            forge(ForgeKind::AzureDevOps).pull_request_ref(3).as_deref(),
+            Some("refs/pull/3/merge")
+        );
+        assert!(forge(ForgeKind::Bitbucket).pull_request_ref(1).is_none());
+        assert!(forge(ForgeKind::Generic).pull_request_ref(1).is_none());
+    }
+
+    #[test]
+    fn test_generic_has_no_api() {
+        let f = forge(ForgeKind::Generic);
+        assert!(f.list_repos("git.example.com", "o").is_err());
+        // Bitbucket Data Center is refused before anything is requested
+        assert!(forge(ForgeKind::Bitbucket)
+            .list_repos("bitbucket.corp.example", "o")
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_github_repo() {
+        let v = json!({
+            "full_name": "o/r",
+            "clone_url": "https://github.com/o/r.git",
+            "ssh_url": "git@github.com:o/r.git",
+            "description": null,
+            "archived": true
+        });
+        let repo = GitHub::parse_repo(&v).expect("parse");
+        assert_eq!(repo.path, "o/r");
+        assert_eq!(repo.clone_url, "https://github.com/o/r.git");
+        assert!(repo.description.is_none());
+        assert!(repo.archived);
+    }
+
+    #[test]
+    fn test_parse_bitbucket_repo() {
+        let v = json!({
+            "full_name": "o/r",
+            "description": "",
+            "mainbranch": { "name": "develop" },
+            "links": { "clone": [
+                { "name": "https", "href": "https://bitbucket.org/o/r.git" },
+                { "name": "ssh", "href": "git@bitbucket.org:o/r.git" }
+            ]}
+        });
+        let repo = Bitbucket::parse_repo(&v).expect("parse");
+        assert_eq!(repo.clone_url, "https://bitbucket.org/o/r.git");
+        assert!(repo.description.is_none());
+    }
+
+    #[test]
+    fn test_parse_azure_repo() {
+        let v = json!({
+            "name": "repo",
+            "project": { "name": "proj" },
+            "remoteUrl": "https://org@dev.azure.com/org/proj/_git/repo",
+            "sshUrl": "git@ssh.dev.azure.com:v3/org/proj/repo",
+            "defaultBranch": "refs/heads/main"
+        });
+        let repo = AzureDevOps::parse_repo(&v).expect("parse");
+        assert_eq!(repo.path, "org/proj/repo");
+        assert!(!repo.archived);
+
+        let v = json!({
+            "name": "repo",
+            "project": { "name": "proj" },
+            "remoteUrl": "https://tfs.example.com/Main/proj/_git/repo"
+        });
+        let repo = AzureDevOps::parse_repo(&v).expect("parse");
+        assert_eq!(repo.path, "Main/proj/repo");
+    }
+}
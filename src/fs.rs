@@ -1,12 +1,18 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use dirs::home_dir;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// Build the local path for a repo given root, host and repo path
-/// Example: root=~/Projects, repo=git@github.com:owner/repo.git -> ~/Projects/github.com/owner/repo
-pub fn build_local_repo_path(root: &str, repo: &str, full_host: bool) -> Result<PathBuf> {
-    // Resolve ~ in root (handle "~/..." and "~" only)
-    let root_path = if root.starts_with("~") {
+use crate::config::{Config, LocalConfig};
+use crate::error::Error;
+use crate::forge::ForgeRegistry;
+use crate::spec::{RepoSpec, Scheme};
+use crate::ssh_config::SshConfig;
+
+/// Resolve `~` in the root directory (handles "~/..." and "~" only)
+pub fn expand_root(root: &str) -> Result<PathBuf> {
+    if root.starts_with("~") {
         let mut p = home_dir().ok_or_else(|| anyhow::anyhow!("Couldn't find home directory"))?;
         let suffix = root.trim_start_matches('~');
         if !suffix.is_empty() {
@@ -14,75 +20,423 @@ pub fn build_local_repo_path(root: &str, repo: &str, full_host: bool) -> Result<
             let to_push = suffix.trim_start_matches('/');
             p.push(to_push);
         }
-        p
+        Ok(p)
     } else {
-        PathBuf::from(root)
-    };
+        Ok(PathBuf::from(root))
+    }
+}
 
-    // Extract host and owner/repo
-    let host = if full_host {
-        crate::util::get_host_from_repo_full(repo)?
-    } else {
-        crate::util::get_host_from_repo(repo)?
-    };
+/// How repository URLs map to directories below the root.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    /// Use the full host domain (e.g. `github.com`) instead of its first label
+    pub full_host: bool,
+    /// Append a non-default port to the host directory (`git.corp_2222`)
+    pub include_port: bool,
+    /// Lowercase the owner (first path segment), for forges that treat it
+    /// case-insensitively
+    pub lowercase_owner: bool,
+    /// Placement of `file://` URLs and local paths
+    pub(crate) local: LocalConfig,
+    /// Host aliases from `~/.ssh/config`
+    pub(crate) ssh: SshConfig,
+    /// Forges whose URLs need their own layout (e.g. Azure DevOps)
+    pub(crate) forges: ForgeRegistry,
+}
 
-    // Extract path part preserving nested segments (groups/subgroups/repo)
-    let path_part = if repo.contains(":") && repo.contains("@") {
-        // scp-style: git@host:owner/subgroup/repo.git -> take after ':'
-        let idx = repo.find(":").unwrap();
-        let after = &repo[idx + 1..];
-        // remove .git suffix if present
-        let trimmed = after.trim_end_matches(".git");
-        trimmed.to_string()
-    } else if repo.contains("/") {
-        // URL or plain host/path. Try to parse after host segment
-        // For URLs like https://host/owner/sub/repo.git or plain host/path like host/owner/repo.git
-        // Split on '/', drop leading scheme+host if present
-        let parts: Vec<&str> = repo.split('/').collect();
-        // If repo starts with a scheme like "https:", shift to get host at index 2
-        if parts.len() >= 3 && parts[0].ends_with(":") {
-            // e.g. ["https:", "", "github.com", "owner", "repo.git"]
-            if parts.len() >= 4 {
-                let relevant = &parts[3..];
-                let joined = relevant.join("/").trim_end_matches(".git").to_string();
-                joined
-            } else {
-                String::new()
-            }
-        } else if parts.len() >= 2 {
-            // plain host/path like github.com/owner/repo.git -> drop the host
-            let relevant = &parts[1..];
-            let joined = relevant.join("/").trim_end_matches(".git").to_string();
-            joined
+impl Layout {
+    /// The layout set up in the user's config file and `~/.ssh/config`. The
+    /// path options start off and can be set on the result.
+    pub fn load() -> crate::Result<Layout> {
+        Ok(Layout::new(&crate::config::load()?, false, false, false))
+    }
+
+    pub(crate) fn new(
+        config: &Config,
+        full_host: bool,
+        include_port: bool,
+        lowercase_owner: bool,
+    ) -> Layout {
+        Layout {
+            full_host,
+            include_port,
+            lowercase_owner,
+            local: config.local.clone(),
+            ssh: SshConfig::load(),
+            forges: ForgeRegistry::from_config(config),
+        }
+    }
+
+    /// Build the local path for `repo` below `root`.
+    /// Example: root=~/Projects, repo=git@github.com:owner/repo.git -> ~/Projects/github/owner/repo
+    pub fn local_path(&self, root: &str, repo: &str) -> crate::Result<PathBuf> {
+        let root = expand_root(root)?;
+        let mut local = root.clone();
+        let spec = RepoSpec::parse(repo)?;
+
+        let (host_dir, mut segments): (String, Vec<String>) = if spec.is_local() {
+            (
+                self.local.host.clone(),
+                self.strip_local_prefix(&spec).to_vec(),
+            )
         } else {
-            String::new()
+            // An ssh alias is laid out under the host it stands for
+            let real_host = self.ssh_hostname(&spec);
+            let forge = self
+                .forges
+                .forge_for_host(real_host.as_deref().unwrap_or(&spec.host));
+            let host = match (forge.layout_host(self.full_host), real_host.as_deref()) {
+                (Some(dir), _) => dir.to_string(),
+                (None, Some(real)) if self.full_host => real.to_string(),
+                (None, Some(real)) => real.split('.').next().unwrap_or(real).to_string(),
+                (None, None) if self.full_host => crate::util::get_host_from_repo_full(repo)?,
+                (None, None) => crate::util::get_host_from_repo(repo)?,
+            };
+            // Ports are ignored unless asked for, so the same repository
+            // reached over https and ssh://host:2222 shares one directory
+            let host = match spec.port {
+                Some(port) if self.include_port => format!("{}_{}", host, port),
+                _ => host,
+            };
+            let segments =
+                forge.layout_segments(real_host.as_deref().unwrap_or(&spec.host), &spec.segments);
+            (host, segments)
+        };
+        if self.lowercase_owner && segments.len() > 1 && !spec.is_local() {
+            segments[0] = segments[0].to_lowercase();
+        }
+
+        // Every component comes from the URL, so none may climb out of the root
+        for seg in std::iter::once(&host_dir).chain(&segments) {
+            validate_segment(seg).map_err(|reason| Error::UnsafePath {
+                repo: spec.to_string(),
+                reason,
+            })?;
+        }
+        local.push(&host_dir);
+        // Nested segments (groups/subgroups/repo) become nested directories
+        for seg in &segments {
+            local.push(seg);
+        }
+        let inside = local
+            .strip_prefix(&root)
+            .is_ok_and(|rel| rel.components().all(|c| matches!(c, Component::Normal(_))));
+        if !inside {
+            return Err(Error::UnsafePath {
+                repo: spec.to_string(),
+                reason: format!("path outside {}", root.display()),
+            });
         }
+
+        Ok(local)
+    }
+
+    /// The `HostName` an ssh URL's host is an alias for in `~/.ssh/config`.
+    fn ssh_hostname(&self, spec: &RepoSpec) -> Option<String> {
+        match spec.scheme {
+            Scheme::Ssh | Scheme::Scp => self.ssh.hostname(&spec.host),
+            _ => None,
+        }
+    }
+
+    /// The segments of a local source below the configured `strip_prefix`.
+    fn strip_local_prefix<'a>(&self, spec: &'a RepoSpec) -> &'a [String] {
+        let Some(prefix) = &self.local.strip_prefix else {
+            return &spec.segments;
+        };
+        let prefix: Vec<&str> = prefix
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => part.to_str(),
+                _ => None,
+            })
+            .collect();
+        let matches = spec.segments.len() > prefix.len()
+            && spec.segments.iter().zip(&prefix).all(|(a, b)| a == b);
+        if !matches {
+            return &spec.segments;
+        }
+        &spec.segments[prefix.len()..]
+    }
+}
+
+/// Check that a URL-derived path segment names a single, ordinary directory
+/// on every platform, so joining it onto the root can't escape it.
+fn validate_segment(seg: &str) -> Result<(), String> {
+    let problem = if seg.is_empty() {
+        "empty path segment"
+    } else if seg == "." || seg == ".." {
+        "relative path segment"
+    } else if seg.chars().any(char::is_control) {
+        "control character in path segment"
+    } else if seg.contains(['/', '\\']) {
+        "path separator in segment"
+    } else if is_drive_prefix(seg) {
+        "absolute path segment"
     } else {
-        String::new()
+        return Ok(());
     };
+    Err(format!("{}: {:?}", problem, seg))
+}
 
-    let mut local = root_path;
-    local.push(host);
-    if !path_part.is_empty() {
-        for seg in path_part.split('/') {
-            local.push(seg);
+/// `C:` and friends, which make a Windows path absolute (or drive-relative).
+fn is_drive_prefix(seg: &str) -> bool {
+    let bytes = seg.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// What currently occupies the path a repository is about to be cloned into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetState {
+    Missing,
+    EmptyDir,
+    /// A directory with the given number of entries
+    NonEmptyDir(usize),
+    NotADirectory,
+}
+
+/// Inspect the clone target. Callers check for an existing repository first.
+pub fn inspect_target(path: &Path) -> Result<TargetState> {
+    if !path.exists() {
+        return Ok(TargetState::Missing);
+    }
+    if !path.is_dir() {
+        return Ok(TargetState::NotADirectory);
+    }
+    let entries = fs::read_dir(path)
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .count();
+    if entries == 0 {
+        Ok(TargetState::EmptyDir)
+    } else {
+        Ok(TargetState::NonEmptyDir(entries))
+    }
+}
+
+/// Move whatever is at `path` aside to a timestamped sibling such as
+/// `repo.backup-1700000000` and return the backup location.
+pub fn backup_existing(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid local path"))?
+        .to_string_lossy()
+        .to_string();
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut backup = path.with_file_name(format!("{}.backup-{}", name, stamp));
+    let mut n = 1;
+    while backup.exists() {
+        backup = path.with_file_name(format!("{}.backup-{}-{}", name, stamp, n));
+        n += 1;
+    }
+    fs::rename(path, &backup).with_context(|| {
+        format!(
+            "failed to move {} aside to {}",
+            path.display(),
+            backup.display()
+        )
+    })?;
+    Ok(backup)
+}
+
+/// Delete the repository at `repo`, then the parent directories (host,
+/// owner, ...) it leaves empty, stopping at `root`. Returns the directories
+/// removed, or that would be with `dry_run`.
+pub fn remove_repo(root: &Path, repo: &Path, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let repo = repo
+        .canonicalize()
+        .with_context(|| format!("failed to resolve {}", repo.display()))?;
+    // Parents are only pruned inside the root, so a missing root prunes nothing
+    let root = root.canonicalize().ok();
+    if root.as_ref().is_some_and(|root| root.starts_with(&repo)) {
+        anyhow::bail!(
+            "refusing to remove {}: it contains the root",
+            repo.display()
+        );
+    }
+
+    if !dry_run {
+        fs::remove_dir_all(&repo)
+            .with_context(|| format!("failed to remove {}", repo.display()))?;
+    }
+    let mut removed = vec![repo.clone()];
+    if let Some(root) = &root {
+        removed.extend(prune_empty_parents(root, &repo, dry_run)?);
+    }
+    Ok(removed)
+}
+
+/// Remove the parents of `child` below `root` that hold nothing but `child`
+/// (already gone, or about to be with `dry_run`), innermost first.
+fn prune_empty_parents(root: &Path, child: &Path, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    let mut child = child;
+    while let Some(parent) = child.parent() {
+        if parent == root || !parent.starts_with(root) {
+            break;
+        }
+        let empty = fs::read_dir(parent)
+            .with_context(|| format!("failed to read {}", parent.display()))?
+            .filter_map(|e| e.ok())
+            .all(|e| e.path() == child);
+        if !empty {
+            break;
+        }
+        if !dry_run {
+            fs::remove_dir(parent)
+                .with_context(|| format!("failed to remove {}", parent.display()))?;
         }
+        removed.push(parent.to_path_buf());
+        child = parent;
     }
+    Ok(removed)
+}
+
+/// A repository whose directory doesn't match where the layout puts its
+/// origin URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Why the move can't be made, if it can't
+    pub conflict: Option<String>,
+}
 
-    Ok(local)
+/// Compare each repository's directory with the path its origin URL maps to
+/// and plan a move for every one that is misplaced. Moves onto an existing
+/// path, into another repository, into the repository itself, or onto a
+/// path claimed by another move are marked as conflicts.
+pub fn plan_relocations(
+    root: &str,
+    repos: &[(PathBuf, String)],
+    layout: &Layout,
+) -> Result<Vec<Relocation>> {
+    let root_dir = expand_root(root)?;
+    let root_canonical = root_dir.canonicalize().ok();
+    let mut plan: Vec<Relocation> = Vec::new();
+    for (from, origin) in repos {
+        // Spell `from` the way the layout spells paths below the root
+        let from = match (from.canonicalize(), &root_canonical) {
+            (Ok(path), Some(root_canonical)) => path
+                .strip_prefix(root_canonical)
+                .map(|rel| root_dir.join(rel))
+                .unwrap_or(path),
+            _ => from.clone(),
+        };
+        let to = layout.local_path(root, origin)?;
+        if to == from || (to.exists() && to.canonicalize().ok() == from.canonicalize().ok()) {
+            continue;
+        }
+        let conflict = if to.starts_with(&from) {
+            Some("the new path is inside the repository".to_string())
+        } else if !matches!(
+            inspect_target(&to)?,
+            TargetState::Missing | TargetState::EmptyDir
+        ) {
+            Some(format!("{} already exists", to.display()))
+        } else if let Some(other) = plan.iter().find(|r| r.to == to) {
+            Some(format!("{} moves there too", other.from.display()))
+        } else {
+            to.ancestors()
+                .skip(1)
+                .take_while(|a| *a != root_dir)
+                .find(|a| crate::git::repo_layout(a).is_some())
+                .map(|a| format!("{} is inside the repository {}", to.display(), a.display()))
+        };
+        plan.push(Relocation { from, to, conflict });
+    }
+    Ok(plan)
+}
+
+/// Move a repository from `from` to `to`, creating the new parents and
+/// removing the old ones it leaves empty below `root`.
+pub fn relocate_repo(root: &Path, from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    // An empty directory in the way would make the rename fail on Windows
+    if inspect_target(to)? == TargetState::EmptyDir {
+        fs::remove_dir(to).with_context(|| format!("failed to remove {}", to.display()))?;
+    }
+    fs::rename(from, to)
+        .with_context(|| format!("failed to move {} to {}", from.display(), to.display()))?;
+    prune_empty_parents(root, from, false)?;
+    Ok(())
+}
+
+/// Resolve a command target that is either a directory inside the root or a
+/// repository URL mapped through `layout`. A directory outside the root is
+/// taken as a URL too, so a local source path names its mirror rather than
+/// the source itself.
+pub fn resolve_mirror(root: &str, target: &str, layout: &Layout) -> Result<PathBuf> {
+    let as_path = PathBuf::from(target);
+    let inside_root = match (as_path.canonicalize(), expand_root(root)?.canonicalize()) {
+        (Ok(path), Ok(root)) => as_path.is_dir() && path.starts_with(root),
+        _ => false,
+    };
+    if inside_root {
+        return Ok(as_path);
+    }
+    Ok(layout.local_path(root, target)?)
+}
+
+/// Find every git repository below `root`. Directories that are repositories
+/// are not descended into, and hidden directories and linked worktrees are
+/// skipped.
+pub fn find_repos(root: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    collect_repos(root, &mut found);
+    found
+}
+
+fn collect_repos(dir: &Path, found: &mut Vec<PathBuf>) {
+    // Asking git about every directory walked would fork it hundreds of times
+    if crate::git::repo_layout(dir).is_some() {
+        // Linked worktrees belong to a repository found elsewhere
+        if !crate::git::is_linked_worktree(dir) {
+            found.push(dir.to_path_buf());
+        }
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut children: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .collect();
+    children.sort();
+    for child in children {
+        collect_repos(&child, found);
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::build_local_repo_path;
+    use super::{
+        backup_existing, find_repos, inspect_target, plan_relocations, relocate_repo, remove_repo,
+        resolve_mirror, Layout, TargetState,
+    };
+    use crate::config::LocalConfig;
+    use crate::ssh_config::SshConfig;
     use dirs::home_dir;
+    use std::env;
+    use std::fs;
     use std::path::PathBuf;
 
     #[test]
     fn test_https_path() {
         let root = "~/Projects";
         let repo = "https://github.com/owner/repo.git";
-        let got = build_local_repo_path(root, repo, false).expect("build path");
+        let got = Layout::default()
+            .local_path(root, repo)
+            .expect("build path");
         let mut expected = home_dir().expect("home_dir");
         expected.push("Projects");
         expected.push("github");
@@ -95,7 +449,9 @@ mod tests {
     fn test_git_plus_https_path() {
         let root = "~/Projects";
         let repo = "git+https://gitlab.com/owner/repo.git";
-        let got = build_local_repo_path(root, repo, false).expect("build path");
+        let got = Layout::default()
+            .local_path(root, repo)
+            .expect("build path");
         let mut expected = home_dir().expect("home_dir");
         expected.push("Projects");
         expected.push("gitlab");
@@ -108,7 +464,9 @@ mod tests {
     fn test_scp_style_path() {
         let root = "~/Projects";
         let repo = "git@bitbucket.org:owner/repo.git";
-        let got = build_local_repo_path(root, repo, false).expect("build path");
+        let got = Layout::default()
+            .local_path(root, repo)
+            .expect("build path");
         let mut expected = home_dir().expect("home_dir");
         expected.push("Projects");
         expected.push("bitbucket");
@@ -121,7 +479,9 @@ mod tests {
     fn test_custom_root() {
         let root = "/tmp/work";
         let repo = "git@github.com:owner/repo.git";
-        let got = build_local_repo_path(root, repo, false).expect("build path");
+        let got = Layout::default()
+            .local_path(root, repo)
+            .expect("build path");
         let mut expected = PathBuf::from("/tmp/work");
         expected.push("github");
         expected.push("owner");
@@ -129,11 +489,288 @@ mod tests {
         assert_eq!(got, expected);
     }
 
+    #[test]
+    fn test_scp_style_other_user_path() {
+        let root = "/tmp/work";
+        let repo = "deploy@git.example.com:team/sub/app.git/";
+        let got = Layout::default()
+            .local_path(root, repo)
+            .expect("build path");
+        assert_eq!(got, PathBuf::from("/tmp/work/git/team/sub/app"));
+    }
+
+    #[test]
+    fn test_local_source_paths() {
+        let layout = Layout::default();
+        for repo in [
+            "file:///srv/git/project.git",
+            "/srv/git/project.git",
+            "/srv/git/project/",
+            "/srv/git/./other/../project",
+        ] {
+            let got = layout.local_path("/tmp/work", repo).expect("build path");
+            assert_eq!(
+                got,
+                PathBuf::from("/tmp/work/local/srv/git/project"),
+                "{}",
+                repo
+            );
+        }
+
+        let layout = Layout {
+            full_host: true,
+            local: LocalConfig {
+                host: "nfs".to_string(),
+                strip_prefix: Some(PathBuf::from("/srv/git")),
+            },
+            ..Layout::default()
+        };
+        let got = layout
+            .local_path("/tmp/work", "file:///srv/git/team/project.git")
+            .expect("build path");
+        assert_eq!(got, PathBuf::from("/tmp/work/nfs/team/project"));
+        // Sources outside the prefix keep their full path
+        let got = layout
+            .local_path("/tmp/work", "/opt/mirror/project.git")
+            .expect("build path");
+        assert_eq!(got, PathBuf::from("/tmp/work/nfs/opt/mirror/project"));
+    }
+
+    #[test]
+    fn test_ports() {
+        let cases = [
+            ("ssh://git@git.corp:2222/team/app.git", "git.corp", "2222"),
+            ("git.corp:2222/team/app.git", "git.corp", "2222"),
+            ("https://git.corp:8443/team/app", "git.corp", "8443"),
+            ("ssh://git@git.corp:22/team/app.git", "git.corp", ""),
+            ("https://git.corp:443/team/app.git", "git.corp", ""),
+        ];
+        for (repo, host, port) in cases {
+            let mut layout = Layout {
+                full_host: true,
+                ..Layout::default()
+            };
+            let got = layout.local_path("/tmp/work", repo).expect("build path");
+            assert_eq!(
+                got,
+                PathBuf::from(format!("/tmp/work/{}/team/app", host)),
+                "{}",
+                repo
+            );
+
+            layout.include_port = true;
+            let dir = if port.is_empty() {
+                host.to_string()
+            } else {
+                format!("{}_{}", host, port)
+            };
+            let got = layout.local_path("/tmp/work", repo).expect("build path");
+            assert_eq!(
+                got,
+                PathBuf::from(format!("/tmp/work/{}/team/app", dir)),
+                "{}",
+                repo
+            );
+        }
+
+        // With a user, scp-like syntax has no port: the digits are a path segment
+        let layout = Layout {
+            include_port: true,
+            ..Layout::default()
+        };
+        let got = layout
+            .local_path("/tmp/work", "git@git.corp:2222/team/app.git")
+            .expect("build path");
+        assert_eq!(got, PathBuf::from("/tmp/work/git/2222/team/app"));
+    }
+
+    #[test]
+    fn test_ssh_alias_path() {
+        let mut dir = env::temp_dir();
+        dir.push("git_mirror_ssh_alias_layout");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create dir");
+        fs::write(
+            dir.join("config"),
+            "Host github-work\n  HostName github.com\n",
+        )
+        .expect("write ssh config");
+        let mut layout = Layout {
+            ssh: SshConfig::load_from(&dir.join("config"), &dir),
+            ..Layout::default()
+        };
+
+        for repo in [
+            "github-work:our-company/api.git",
+            "git@github-work:our-company/api.git",
+            "ssh://git@github-work/our-company/api.git",
+        ] {
+            let got = layout.local_path("/tmp/work", repo).expect("build path");
+            assert_eq!(
+                got,
+                PathBuf::from("/tmp/work/github/our-company/api"),
+                "{}",
+                repo
+            );
+        }
+        layout.full_host = true;
+        let got = layout
+            .local_path("/tmp/work", "github-work:our-company/api.git")
+            .expect("build path");
+        assert_eq!(got, PathBuf::from("/tmp/work/github.com/our-company/api"));
+
+        // Aliases only apply to ssh transports
+        let got = layout
+            .local_path("/tmp/work", "https://github-work.example/o/r")
+            .expect("build path");
+        assert_eq!(got, PathBuf::from("/tmp/work/github-work.example/o/r"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_unsafe_segments_rejected() {
+        let layout = Layout::default();
+        for repo in [
+            "git@github.com:../../.ssh/x.git",
+            "git@github.com:owner/../../../etc/x.git",
+            "github.com/owner/../../x",
+            "git@github.com:owner/./repo.git",
+            "https://github.com/owner//repo.git",
+            "git@github.com:owner//repo.git",
+            "git@github.com:owner/re\0po.git",
+            "git@github.com:owner/re\x1bpo.git",
+            "git@github.com:owner/..\\..\\x.git",
+            "https://github.com/owner/C:/repo.git",
+            "git@github.com:C:\\Windows/repo.git",
+            "..:owner/repo.git",
+        ] {
+            let err = layout
+                .local_path("/tmp/work", repo)
+                .expect_err(&format!("{:?} should be rejected", repo));
+            assert!(
+                format!("{:#}", err).contains("unsafe"),
+                "{:?}: {:#}",
+                repo,
+                err
+            );
+        }
+
+        let layout = Layout {
+            local: LocalConfig {
+                host: "../escape".to_string(),
+                strip_prefix: None,
+            },
+            ..Layout::default()
+        };
+        assert!(layout.local_path("/tmp/work", "/srv/git/x.git").is_err());
+    }
+
+    #[test]
+    fn test_paths_stay_inside_root() {
+        let layout = Layout::default();
+        let root = PathBuf::from("/tmp/work");
+        for repo in [
+            // URL parsing already resolves dot segments for http(s)
+            "https://host.example/../../.ssh/x.git",
+            "https://host.example/a/%2e%2e/%2E%2E/b.git",
+            "ssh://git@host.example/a/../../b.git",
+            "file:///srv/../../../etc/passwd",
+            "/srv/git/../../../x.git",
+        ] {
+            let got = layout.local_path("/tmp/work", repo).expect("build path");
+            let rel = got.strip_prefix(&root).expect("inside root");
+            assert!(
+                rel.components()
+                    .all(|c| matches!(c, std::path::Component::Normal(_))),
+                "{}: {}",
+                repo,
+                got.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_every_url_form_maps_to_one_directory() {
+        let layout = Layout {
+            lowercase_owner: true,
+            ..Layout::default()
+        };
+        for repo in [
+            "https://GitHub.com/Owner/my%20repo.git",
+            "git@github.com:owner/my repo.git",
+            "ssh://git@github.com/OWNER/my%20repo",
+            "github.com/owner/my%20repo/",
+        ] {
+            let got = layout.local_path("/tmp/work", repo).expect("build path");
+            assert_eq!(
+                got,
+                PathBuf::from("/tmp/work/github/owner/my repo"),
+                "{}",
+                repo
+            );
+        }
+
+        // Without the option the owner keeps its case; the host never does
+        let got = Layout::default()
+            .local_path("/tmp/work", "https://GitHub.com/Owner/Repo")
+            .expect("build path");
+        assert_eq!(got, PathBuf::from("/tmp/work/github/Owner/Repo"));
+
+        assert!(Layout::default()
+            .local_path("/tmp/work", "https://github.com/owner/a%2F..%2F..%2Fx")
+            .is_err());
+    }
+
+    #[test]
+    fn test_azure_devops_paths() {
+        let mut layout = Layout::default();
+        for repo in [
+            "https://dev.azure.com/org/project/_git/repo",
+            "https://org@dev.azure.com/org/project/_git/repo",
+            "git@ssh.dev.azure.com:v3/org/project/repo",
+            "ssh://git@ssh.dev.azure.com/v3/org/project/repo",
+            "https://org.visualstudio.com/project/_git/repo",
+            "https://org.visualstudio.com/DefaultCollection/project/_git/repo",
+            "org@vs-ssh.visualstudio.com:v3/org/project/repo",
+        ] {
+            let got = layout.local_path("/tmp/work", repo).expect("build path");
+            assert_eq!(
+                got,
+                PathBuf::from("/tmp/work/azure/org/project/repo"),
+                "{}",
+                repo
+            );
+        }
+
+        layout.full_host = true;
+        let got = layout
+            .local_path("/tmp/work", "git@ssh.dev.azure.com:v3/org/project/repo")
+            .expect("build path");
+        assert_eq!(
+            got,
+            PathBuf::from("/tmp/work/dev.azure.com/org/project/repo")
+        );
+
+        // Project names with spaces arrive percent-encoded over https
+        layout.full_host = false;
+        let got = layout
+            .local_path(
+                "/tmp/work",
+                "https://dev.azure.com/org/My%20Project/_git/repo",
+            )
+            .expect("build path");
+        assert_eq!(got, PathBuf::from("/tmp/work/azure/org/My Project/repo"));
+    }
+
     #[test]
     fn test_full_host_true() {
         let root = "~/Projects";
         let repo = "git@github.com:owner/repo.git";
-        let got = build_local_repo_path(root, repo, true).expect("build path");
+        let layout = Layout {
+            full_host: true,
+            ..Layout::default()
+        };
+        let got = layout.local_path(root, repo).expect("build path");
         let mut expected = home_dir().expect("home_dir");
         expected.push("Projects");
         expected.push("github.com");
@@ -141,4 +778,201 @@ mod tests {
         expected.push("repo");
         assert_eq!(got, expected);
     }
+
+    #[test]
+    fn test_find_repos() {
+        let mut root = env::temp_dir();
+        root.push("git_mirror_find_repos_unit");
+        let _ = fs::remove_dir_all(&root);
+        for repo in ["github/a/one", "github/b/two", "gitlab/g/sub/three"] {
+            fs::create_dir_all(root.join(repo).join(".git")).expect("create repo");
+        }
+        // nested directories inside a repository are not reported
+        fs::create_dir_all(root.join("github/a/one/vendor/x/.git")).expect("create nested");
+        fs::create_dir_all(root.join("github/empty")).expect("create empty");
+        // a linked worktree next to its repository is not a separate mirror
+        fs::create_dir_all(root.join("github/a/one/.git/worktrees/one.pr-1")).expect("create");
+        fs::create_dir_all(root.join("github/a/one.pr-1")).expect("create worktree");
+        fs::write(
+            root.join("github/a/one.pr-1/.git"),
+            format!(
+                "gitdir: {}\n",
+                root.join("github/a/one/.git/worktrees/one.pr-1").display()
+            ),
+        )
+        .expect("write gitfile");
+
+        let found = find_repos(&root);
+        let expected: Vec<PathBuf> = ["github/a/one", "github/b/two", "gitlab/g/sub/three"]
+            .iter()
+            .map(|r| root.join(r))
+            .collect();
+        assert_eq!(found, expected);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_plan_and_relocate() {
+        let mut root = env::temp_dir();
+        root.push("git_mirror_relocate_unit");
+        let _ = fs::remove_dir_all(&root);
+        for repo in [
+            "github/old-org/app",
+            "github/me/old-name",
+            "github/me/placed",
+            "github/taken/x",
+            "github/dup-a/r",
+            "github/dup-b/r",
+            "github/nested/lib",
+        ] {
+            fs::create_dir_all(root.join(repo).join(".git")).expect("create repo");
+        }
+        let root_str = root.to_str().unwrap().to_string();
+        let repos: Vec<(PathBuf, String)> = [
+            ("github/old-org/app", "git@github.com:new-org/app.git"),
+            ("github/me/old-name", "https://github.com/me/new-name"),
+            ("github/me/placed", "git@github.com:me/placed.git"),
+            ("github/taken/x", "git@github.com:me/placed.git"),
+            ("github/dup-a/r", "git@github.com:same/r.git"),
+            ("github/dup-b/r", "git@github.com:same/r.git"),
+            ("github/nested/lib", "git@github.com:me/placed/lib.git"),
+        ]
+        .iter()
+        .map(|(dir, url)| (root.join(dir), url.to_string()))
+        .collect();
+
+        let plan = plan_relocations(&root_str, &repos, &Layout::default()).expect("plan");
+        let summary: Vec<(PathBuf, bool)> = plan
+            .iter()
+            .map(|r| {
+                (
+                    r.to.strip_prefix(&root).unwrap().to_path_buf(),
+                    r.conflict.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (PathBuf::from("github/new-org/app"), false),
+                (PathBuf::from("github/me/new-name"), false),
+                (PathBuf::from("github/me/placed"), true),
+                (PathBuf::from("github/same/r"), false),
+                (PathBuf::from("github/same/r"), true),
+                (PathBuf::from("github/me/placed/lib"), true),
+            ]
+        );
+        assert!(plan[4].conflict.as_deref().unwrap().contains("dup-a"));
+
+        relocate_repo(&root, &plan[0].from, &plan[0].to).expect("relocate");
+        assert!(root.join("github/new-org/app/.git").is_dir());
+        // The emptied owner directory is pruned, the host directory stays
+        assert!(!root.join("github/old-org").exists());
+        assert!(root.join("github").is_dir());
+
+        let moved = vec![(root.join("github/new-org/app"), repos[0].1.clone())];
+        assert!(plan_relocations(&root_str, &moved, &Layout::default())
+            .expect("plan")
+            .is_empty());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_resolve_mirror_ignores_sources_outside_root() {
+        let mut base = env::temp_dir();
+        base.push("git_mirror_resolve_mirror");
+        let _ = fs::remove_dir_all(&base);
+        let source = base.join("src").join("app.git");
+        let mirror = base.join("root").join("github").join("o").join("r");
+        fs::create_dir_all(&source).expect("create source");
+        fs::create_dir_all(&mirror).expect("create mirror");
+        let root = base.join("root");
+        let root = root.to_str().unwrap();
+
+        let got =
+            resolve_mirror(root, mirror.to_str().unwrap(), &Layout::default()).expect("resolve");
+        assert_eq!(got, mirror);
+        let got =
+            resolve_mirror(root, source.to_str().unwrap(), &Layout::default()).expect("resolve");
+        assert!(got.starts_with(base.join("root").join("local")));
+        assert!(got.ends_with("src/app"));
+        let got =
+            resolve_mirror(root, "git@github.com:o/r.git", &Layout::default()).expect("resolve");
+        assert_eq!(got, mirror);
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_inspect_target_and_backup() {
+        let mut base = env::temp_dir();
+        base.push("git_mirror_inspect_target_unit");
+        let _ = fs::remove_dir_all(&base);
+        let target = base.join("repo");
+        assert_eq!(
+            inspect_target(&target).expect("inspect"),
+            TargetState::Missing
+        );
+
+        fs::create_dir_all(&target).expect("create target");
+        assert_eq!(
+            inspect_target(&target).expect("inspect"),
+            TargetState::EmptyDir
+        );
+
+        fs::write(target.join("leftover.txt"), "x").expect("write");
+        assert_eq!(
+            inspect_target(&target).expect("inspect"),
+            TargetState::NonEmptyDir(1)
+        );
+
+        let backup = backup_existing(&target).expect("backup");
+        assert!(!target.exists());
+        assert!(backup.join("leftover.txt").exists());
+        assert!(backup
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("repo.backup-"));
+
+        fs::write(&target, "file").expect("write file");
+        assert_eq!(
+            inspect_target(&target).expect("inspect"),
+            TargetState::NotADirectory
+        );
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_remove_repo_prunes_empty_parents() {
+        let mut root = env::temp_dir();
+        root.push("git_mirror_remove_repo");
+        let _ = fs::remove_dir_all(&root);
+        let repo = root.join("github").join("owner").join("repo");
+        let sibling = root.join("github").join("other").join("repo");
+        fs::create_dir_all(repo.join(".git")).expect("create repo");
+        fs::create_dir_all(&sibling).expect("create sibling");
+        let root = root.canonicalize().expect("canonicalize");
+        let repo = repo.canonicalize().expect("canonicalize");
+
+        let planned = remove_repo(&root, &repo, true).expect("dry run");
+        assert_eq!(
+            planned,
+            vec![repo.clone(), root.join("github").join("owner")]
+        );
+        assert!(repo.exists());
+
+        let removed = remove_repo(&root, &repo, false).expect("remove");
+        assert_eq!(removed, planned);
+        assert!(!root.join("github").join("owner").exists());
+        assert!(sibling.exists());
+
+        // Removing the last repository empties the host directory too
+        let removed = remove_repo(&root, &sibling, false).expect("remove");
+        assert_eq!(removed.len(), 3);
+        assert!(root.exists());
+        assert!(!root.join("github").exists());
+
+        assert!(remove_repo(&root, &root, true).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
 }
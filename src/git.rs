@@ -1,17 +1,142 @@
 use anyhow::{Context, Result};
+use serde::Serialize;
+use signal_hook::consts::SIGINT;
+use signal_hook::SigId;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
 use which::which;
 
-fn clone_repo_with_writer<W: Write>(
+use crate::error::Error;
+
+/// Ctrl-C handling for the duration of a clone: an interrupt only records
+/// itself, so the staging directory can be cleaned up once git exits.
+/// Dropping the guard hands the signal back to its default action.
+struct InterruptGuard {
+    id: SigId,
+    interrupted: Arc<AtomicBool>,
+}
+
+/// False while a clone is running. signal-hook can't restore the original
+/// disposition, so outside a clone its default action is emulated instead.
+static IDLE: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+impl InterruptGuard {
+    fn install() -> Result<InterruptGuard> {
+        let idle = IDLE.get_or_init(|| {
+            let idle = Arc::new(AtomicBool::new(true));
+            let _ = signal_hook::flag::register_conditional_default(SIGINT, Arc::clone(&idle));
+            idle
+        });
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let id = signal_hook::flag::register(SIGINT, Arc::clone(&interrupted))
+            .context("failed to install interrupt handler")?;
+        idle.store(false, Ordering::SeqCst);
+        Ok(InterruptGuard { id, interrupted })
+    }
+
+    fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        signal_hook::low_level::unregister(self.id);
+        if let Some(idle) = IDLE.get() {
+            idle.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Removes the staging directory when dropped unless the clone was moved into place.
+struct StagingDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// Locate the `git` executable.
+fn git_binary() -> Result<PathBuf> {
+    which("git").map_err(|_| Error::GitNotFound.into())
+}
+
+/// Return the hidden sibling directory a clone of `local_path` is staged in.
+fn staging_path(local_path: &Path) -> Result<PathBuf> {
+    let name = local_path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid local path"))?;
+    Ok(local_path.with_file_name(format!(
+        ".{}.git-mirror-{}",
+        name.to_string_lossy(),
+        std::process::id()
+    )))
+}
+
+/// Remove staging directories for `local_path` left behind by runs that were
+/// killed before they could clean up.
+fn remove_stale_staging(local_path: &Path) {
+    let (Some(parent), Some(name)) = (local_path.parent(), local_path.file_name()) else {
+        return;
+    };
+    let prefix = format!(".{}.git-mirror-", name.to_string_lossy());
+    let Ok(entries) = fs::read_dir(parent) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let pid = entry
+            .file_name()
+            .to_str()
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|p| p.parse::<u32>().ok());
+        // Another run may be cloning the same repository right now
+        if pid.is_some_and(|pid| !process_running(pid)) {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// Whether a process with this id is running.
+fn process_running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    if cfg!(windows) {
+        Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .output()
+            .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).contains(&pid.to_string()))
+    } else {
+        Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success())
+    }
+}
+
+/// Clone `repo` into `local_path`, creating parent directories as needed.
+/// The clone is made in a staging directory next to the target and renamed
+/// into place only on success, so the target is either absent or complete.
+pub fn clone_repo_with_writer<W: Write>(
     repo: &str,
     local_path: &Path,
     dry_run: bool,
     writer: &mut W,
 ) -> Result<()> {
-    let git = which("git").context("git executable not found in PATH")?;
+    let git = git_binary()?;
 
     if dry_run {
         writeln!(
@@ -28,116 +153,1422 @@ fn clone_repo_with_writer<W: Write>(
         .ok_or_else(|| anyhow::anyhow!("Invalid local path"))?;
     fs::create_dir_all(parent).context("failed to create parent directories")?;
 
+    let mut staging = StagingDir {
+        path: staging_path(local_path)?,
+        keep: false,
+    };
+    let _ = fs::remove_dir_all(&staging.path);
+    remove_stale_staging(local_path);
+
+    let guard = InterruptGuard::install()?;
     let status = Command::new(git)
         .arg("clone")
         .arg(repo)
+        .arg(&staging.path)
+        .status();
+    let interrupted = guard.interrupted();
+    drop(guard);
+
+    if interrupted {
+        return Err(Error::Interrupted.into());
+    }
+    let status = status.context("failed to spawn git clone")?;
+    if !status.success() {
+        return Err(Error::GitStatus {
+            command: "clone".to_string(),
+            status,
+        }
+        .into());
+    }
+
+    // An empty directory may already sit at the target; rename can't replace it everywhere
+    if local_path.is_dir() {
+        fs::remove_dir(local_path)
+            .with_context(|| format!("failed to replace {}", local_path.display()))?;
+    }
+    fs::rename(&staging.path, local_path).with_context(|| {
+        format!(
+            "failed to move clone from {} to {}",
+            staging.path.display(),
+            local_path.display()
+        )
+    })?;
+    staging.keep = true;
+
+    writeln!(
+        writer,
+        "Repository cloned successfully to {}",
+        local_path.display()
+    )?;
+    Ok(())
+}
+
+/// The layout of a repository found on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepoKind {
+    /// A regular checkout with a `.git` directory
+    WorkTree,
+    /// A linked worktree or submodule checkout whose `.git` is a file
+    /// pointing at the real git directory
+    GitFile,
+    /// A bare repository without a working tree
+    Bare,
+}
+
+impl std::fmt::Display for RepoKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepoKind::WorkTree => write!(f, "working tree"),
+            RepoKind::GitFile => write!(f, "linked worktree or submodule checkout"),
+            RepoKind::Bare => write!(f, "bare repository"),
+        }
+    }
+}
+
+/// Detect whether `local_path` is the top level of a git repository and
+/// which kind. Paths merely nested inside another repository don't count.
+/// Layouts the filesystem checks of [`repo_layout`] miss are left to git.
+pub fn detect_repo(local_path: &Path) -> Option<RepoKind> {
+    if !local_path.is_dir() {
+        return None;
+    }
+    repo_layout(local_path).or_else(|| detect_repo_with_git(local_path))
+}
+
+/// Recognise a `.git` directory, a gitfile or a bare repository at
+/// `local_path` from the filesystem alone. Cheap enough for walking a whole
+/// tree of directories, unlike [`detect_repo`].
+pub fn repo_layout(local_path: &Path) -> Option<RepoKind> {
+    let dot_git = local_path.join(".git");
+    if dot_git.is_dir() {
+        return Some(RepoKind::WorkTree);
+    }
+    if dot_git.is_file() {
+        // gitfile: "gitdir: <path>", relative paths resolve against the checkout
+        let contents = fs::read_to_string(&dot_git).ok()?;
+        let target = contents.trim().strip_prefix("gitdir:")?.trim();
+        return local_path
+            .join(target)
+            .is_dir()
+            .then_some(RepoKind::GitFile);
+    }
+
+    // Bare repositories keep HEAD, objects and refs at the top level
+    if local_path.join("HEAD").is_file()
+        && local_path.join("objects").is_dir()
+        && local_path.join("refs").is_dir()
+    {
+        return Some(RepoKind::Bare);
+    }
+    None
+}
+
+/// Whether `local_path` is a linked worktree (`git worktree add`) of another
+/// repository, whose gitfile points into `<repo>/.git/worktrees/`.
+pub fn is_linked_worktree(local_path: &Path) -> bool {
+    let Ok(contents) = fs::read_to_string(local_path.join(".git")) else {
+        return false;
+    };
+    let Some(target) = contents.trim().strip_prefix("gitdir:") else {
+        return false;
+    };
+    Path::new(target.trim())
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|name| name == "worktrees")
+}
+
+/// Ask git itself, for layouts the file checks above don't recognise.
+fn detect_repo_with_git(local_path: &Path) -> Option<RepoKind> {
+    let here = fs::canonicalize(local_path).ok()?;
+    let same_dir = |p: &str| fs::canonicalize(p).map(|p| p == here).unwrap_or(false);
+
+    if let Ok(top) = git_output(local_path, &["rev-parse", "--show-toplevel"]) {
+        return same_dir(&top).then_some(RepoKind::WorkTree);
+    }
+    let out = git_output(
+        local_path,
+        &["rev-parse", "--is-bare-repository", "--absolute-git-dir"],
+    )
+    .ok()?;
+    let mut lines = out.lines();
+    match (lines.next(), lines.next()) {
+        (Some("true"), Some(git_dir)) if same_dir(git_dir) => Some(RepoKind::Bare),
+        _ => None,
+    }
+}
+
+/// Return true if the given local path appears to be an existing git repository
+pub fn repo_exists(local_path: &Path) -> bool {
+    detect_repo(local_path).is_some()
+}
+
+/// A remote-tracking branch that moved during a fetch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BranchUpdate {
+    pub branch: String,
+    /// Commits reachable from the new tip but not the old one
+    pub commits: usize,
+    /// The old tip is not an ancestor of the new one
+    pub forced: bool,
+}
+
+/// What changed upstream during a fetch, computed by comparing refs before and after.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct FetchSummary {
+    pub updated: Vec<BranchUpdate>,
+    pub new_branches: Vec<String>,
+    pub deleted_branches: Vec<String>,
+    pub new_tags: Vec<String>,
+}
+
+impl FetchSummary {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty()
+            && self.new_branches.is_empty()
+            && self.deleted_branches.is_empty()
+            && self.new_tags.is_empty()
+    }
+}
+
+impl std::fmt::Display for FetchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No upstream changes");
+        }
+        let mut lines = Vec::new();
+        for u in &self.updated {
+            if u.forced {
+                lines.push(format!(
+                    "  {} (forced update, {} new commit(s))",
+                    u.branch, u.commits
+                ));
+            } else {
+                lines.push(format!("  {} ({} new commit(s))", u.branch, u.commits));
+            }
+        }
+        for b in &self.new_branches {
+            lines.push(format!("  new branch {}", b));
+        }
+        for b in &self.deleted_branches {
+            lines.push(format!("  deleted branch {}", b));
+        }
+        for t in &self.new_tags {
+            lines.push(format!("  new tag {}", t));
+        }
+        write!(f, "Upstream changes:\n{}", lines.join("\n"))
+    }
+}
+
+/// Map remote-tracking branches and tags to the object they point at.
+/// Symbolic `<remote>/HEAD` refs are left out.
+fn ref_snapshot(local_path: &Path) -> Result<BTreeMap<String, String>> {
+    let listing = git_output(
+        local_path,
+        &[
+            "for-each-ref",
+            "--format=%(objectname)%09%(refname)%09%(symref)",
+            "refs/remotes",
+            "refs/tags",
+        ],
+    )?;
+    Ok(listing
+        .lines()
+        .filter_map(|l| {
+            let mut fields = l.split('\t');
+            let (sha, name, symref) = (fields.next()?, fields.next()?, fields.next());
+            if symref.is_some_and(|s| !s.is_empty()) {
+                return None;
+            }
+            Some((name.to_string(), sha.to_string()))
+        })
+        .collect())
+}
+
+/// Compare two ref snapshots taken before and after a fetch.
+fn summarize_fetch(
+    local_path: &Path,
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+) -> Result<FetchSummary> {
+    let mut summary = FetchSummary::default();
+    for (name, new_sha) in after {
+        if let Some(tag) = name.strip_prefix("refs/tags/") {
+            if !before.contains_key(name) {
+                summary.new_tags.push(tag.to_string());
+            }
+            continue;
+        }
+        let branch = name.trim_start_matches("refs/remotes/").to_string();
+        match before.get(name) {
+            None => summary.new_branches.push(branch),
+            Some(old_sha) if old_sha != new_sha => {
+                let range = format!("{}..{}", old_sha, new_sha);
+                let commits = git_output(local_path, &["rev-list", "--count", &range])?
+                    .parse()
+                    .unwrap_or(0);
+                let forced = !git_succeeds(
+                    local_path,
+                    &["merge-base", "--is-ancestor", old_sha, new_sha],
+                )?;
+                summary.updated.push(BranchUpdate {
+                    branch,
+                    commits,
+                    forced,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    for name in before.keys() {
+        if let Some(branch) = name.strip_prefix("refs/remotes/") {
+            if !after.contains_key(name) {
+                summary.deleted_branches.push(branch.to_string());
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Fetch an existing repository using `git -C <path> fetch --all --prune` and
+/// summarize what changed upstream.
+pub fn fetch_repo_with_writer<W: Write>(
+    local_path: &Path,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<FetchSummary> {
+    fetch_repo(local_path, dry_run, true, writer)
+}
+
+/// Like [`fetch_repo_with_writer`], failing instead of prompting for
+/// credentials or an SSH passphrase.
+pub fn fetch_repo_batch_with_writer<W: Write>(
+    local_path: &Path,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<FetchSummary> {
+    fetch_repo(local_path, dry_run, false, writer)
+}
+
+fn fetch_repo<W: Write>(
+    local_path: &Path,
+    dry_run: bool,
+    prompt: bool,
+    writer: &mut W,
+) -> Result<FetchSummary> {
+    let git = git_binary()?;
+
+    if dry_run {
+        writeln!(
+            writer,
+            "Dry run: git -C {} fetch --all --prune",
+            local_path.display()
+        )?;
+        return Ok(FetchSummary::default());
+    }
+
+    let before = ref_snapshot(local_path)?;
+
+    // git reports progress on stderr; keep stdout free for our own output
+    let mut cmd = Command::new(git);
+    cmd.arg("-C")
+        .arg(local_path)
+        .arg("fetch")
+        .arg("--all")
+        .arg("--prune")
+        .stdout(Stdio::null());
+    if !prompt {
+        cmd.env("GIT_TERMINAL_PROMPT", "0");
+        if std::env::var_os("GIT_SSH_COMMAND").is_none() {
+            cmd.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+        }
+    }
+    let status = cmd.status().context("failed to spawn git fetch")?;
+
+    if !status.success() {
+        return Err(Error::GitStatus {
+            command: "fetch".to_string(),
+            status,
+        }
+        .into());
+    }
+
+    let after = ref_snapshot(local_path)?;
+    summarize_fetch(local_path, &before, &after)
+}
+
+/// How an existing repository is brought up to date after fetching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// Only fetch; never touch local branches
+    Fetch,
+    /// Fast-forward the checked-out branch to its upstream
+    Pull,
+    /// Rebase the checked-out branch onto its upstream
+    Rebase,
+}
+
+/// Outcome of updating the checked-out branch from its upstream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PullOutcome {
+    UpToDate,
+    FastForwarded {
+        branch: String,
+        commits: usize,
+    },
+    Rebased {
+        branch: String,
+        commits: usize,
+    },
+    /// Local commits exist but upstream has nothing new
+    Ahead {
+        branch: String,
+        commits: usize,
+    },
+    Diverged {
+        branch: String,
+        ahead: usize,
+        behind: usize,
+    },
+    /// A rebase stopped on conflicts and was aborted
+    RebaseConflict {
+        branch: String,
+    },
+    Dirty,
+    Detached,
+    NoUpstream {
+        branch: String,
+    },
+}
+
+impl PullOutcome {
+    /// True if the branch moved.
+    pub fn updated(&self) -> bool {
+        matches!(
+            self,
+            PullOutcome::FastForwarded { .. } | PullOutcome::Rebased { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for PullOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PullOutcome::UpToDate => write!(f, "Branch is already up to date"),
+            PullOutcome::FastForwarded { branch, commits } => {
+                write!(f, "Fast-forwarded {} by {} commit(s)", branch, commits)
+            }
+            PullOutcome::Rebased { branch, commits } => write!(
+                f,
+                "Rebased {} onto its upstream ({} new upstream commit(s))",
+                branch, commits
+            ),
+            PullOutcome::Ahead { branch, commits } => write!(
+                f,
+                "{} is {} commit(s) ahead of its upstream; nothing to pull",
+                branch, commits
+            ),
+            PullOutcome::Diverged {
+                branch,
+                ahead,
+                behind,
+            } => write!(
+                f,
+                "Cannot fast-forward {}: diverged from upstream ({} ahead, {} behind)",
+                branch, ahead, behind
+            ),
+            PullOutcome::RebaseConflict { branch } => write!(
+                f,
+                "Rebase of {} hit conflicts and was aborted; resolve manually",
+                branch
+            ),
+            PullOutcome::Dirty => write!(
+                f,
+                "Working tree has uncommitted changes; branch left untouched"
+            ),
+            PullOutcome::Detached => write!(f, "HEAD is detached; nothing to update"),
+            PullOutcome::NoUpstream { branch } => {
+                write!(f, "{} has no upstream branch; nothing to update", branch)
+            }
+        }
+    }
+}
+
+/// Run `git -C <path> <args>` and return its trimmed stdout, failing on a
+/// non-zero exit status.
+fn git_output(local_path: &Path, args: &[&str]) -> Result<String> {
+    let git = git_binary()?;
+    let output = Command::new(git)
+        .arg("-C")
+        .arg(local_path)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to spawn git {}", args.join(" ")))?;
+
+    if !output.status.success() {
+        return Err(Error::Git {
+            command: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Run `git -C <path> <args>` and report whether it exited successfully.
+fn git_succeeds(local_path: &Path, args: &[&str]) -> Result<bool> {
+    let git = git_binary()?;
+    let output = Command::new(git)
+        .arg("-C")
+        .arg(local_path)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to spawn git {}", args.join(" ")))?;
+    Ok(output.status.success())
+}
+
+/// Return true if tracked files have uncommitted changes.
+fn is_dirty(local_path: &Path) -> Result<bool> {
+    Ok(!git_output(
+        local_path,
+        &["status", "--porcelain", "--untracked-files=no"],
+    )?
+    .is_empty())
+}
+
+/// Count commits only in `local` and only in `upstream`.
+fn ahead_behind(local_path: &Path, local: &str, upstream: &str) -> Result<(usize, usize)> {
+    let range = format!("{}...{}", local, upstream);
+    let counts = git_output(local_path, &["rev-list", "--left-right", "--count", &range])?;
+    let mut parts = counts.split_whitespace().map(|n| n.parse::<usize>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(a)), Some(Ok(b))) => Ok((a, b)),
+        _ => anyhow::bail!("unexpected rev-list output: {}", counts),
+    }
+}
+
+/// Update the checked-out branch from its upstream according to `mode`.
+/// Situations where the branch cannot be moved safely (dirty tree, divergence,
+/// missing upstream) are reported through `PullOutcome` rather than as errors.
+pub fn update_current_branch_with_writer<W: Write>(
+    local_path: &Path,
+    mode: UpdateMode,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<PullOutcome> {
+    if dry_run {
+        let cmd = match mode {
+            UpdateMode::Fetch => return Ok(PullOutcome::UpToDate),
+            UpdateMode::Pull => "merge --ff-only @{u}",
+            UpdateMode::Rebase => "rebase @{u}",
+        };
+        writeln!(writer, "Dry run: git -C {} {}", local_path.display(), cmd)?;
+        return Ok(PullOutcome::UpToDate);
+    }
+
+    if mode == UpdateMode::Fetch {
+        return Ok(PullOutcome::UpToDate);
+    }
+
+    let branch = match git_output(local_path, &["symbolic-ref", "--quiet", "--short", "HEAD"]) {
+        Ok(b) => b,
+        Err(_) => return Ok(PullOutcome::Detached),
+    };
+
+    if !git_succeeds(
+        local_path,
+        &["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"],
+    )? {
+        return Ok(PullOutcome::NoUpstream { branch });
+    }
+
+    if is_dirty(local_path)? {
+        return Ok(PullOutcome::Dirty);
+    }
+
+    let (ahead, behind) = ahead_behind(local_path, "HEAD", "@{u}")?;
+
+    if behind == 0 {
+        if ahead > 0 {
+            return Ok(PullOutcome::Ahead {
+                branch,
+                commits: ahead,
+            });
+        }
+        return Ok(PullOutcome::UpToDate);
+    }
+
+    if ahead == 0 {
+        git_output(local_path, &["merge", "--ff-only", "--quiet", "@{u}"])?;
+        return Ok(PullOutcome::FastForwarded {
+            branch,
+            commits: behind,
+        });
+    }
+
+    match mode {
+        UpdateMode::Rebase => {
+            if git_succeeds(local_path, &["rebase", "--quiet", "@{u}"])? {
+                Ok(PullOutcome::Rebased {
+                    branch,
+                    commits: behind,
+                })
+            } else {
+                git_output(local_path, &["rebase", "--abort"])?;
+                Ok(PullOutcome::RebaseConflict { branch })
+            }
+        }
+        _ => Ok(PullOutcome::Diverged {
+            branch,
+            ahead,
+            behind,
+        }),
+    }
+}
+
+/// What happened to each local branch during `fast_forward_branches`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BranchSyncReport {
+    /// Branches moved forward, with the number of new commits
+    pub moved: Vec<(String, usize)>,
+    /// Branches with local commits not on their upstream
+    pub diverged: Vec<String>,
+    /// Branches whose upstream no longer exists
+    pub gone: Vec<String>,
+    /// Branches left alone, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for BranchSyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines = Vec::new();
+        for (branch, commits) in &self.moved {
+            lines.push(format!("  moved     {} (+{})", branch, commits));
+        }
+        for branch in &self.diverged {
+            lines.push(format!("  diverged  {}", branch));
+        }
+        for branch in &self.gone {
+            lines.push(format!("  gone      {} (upstream deleted)", branch));
+        }
+        for (branch, reason) in &self.skipped {
+            lines.push(format!("  skipped   {} ({})", branch, reason));
+        }
+        if lines.is_empty() {
+            return write!(f, "All local branches are up to date");
+        }
+        write!(f, "Local branches:\n{}", lines.join("\n"))
+    }
+}
+
+/// Return the branches checked out in worktrees other than the one at `local_path`.
+fn branches_in_other_worktrees(local_path: &Path, current: Option<&str>) -> Result<Vec<String>> {
+    let listing = git_output(local_path, &["worktree", "list", "--porcelain"])?;
+    Ok(listing
+        .lines()
+        .filter_map(|l| l.strip_prefix("branch refs/heads/"))
+        .filter(|b| Some(*b) != current)
+        .map(str::to_string)
+        .collect())
+}
+
+/// Fast-forward every local branch whose upstream is strictly ahead. Branches
+/// that aren't checked out are moved with `git update-ref`, so the working
+/// tree is never touched; the checked-out branch is only fast-forwarded when
+/// the tree is clean.
+pub fn fast_forward_branches_with_writer<W: Write>(
+    local_path: &Path,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<BranchSyncReport> {
+    if dry_run {
+        writeln!(
+            writer,
+            "Dry run: fast-forward local branches in {}",
+            local_path.display()
+        )?;
+        return Ok(BranchSyncReport::default());
+    }
+
+    let current = git_output(local_path, &["symbolic-ref", "--quiet", "--short", "HEAD"]).ok();
+    let elsewhere = branches_in_other_worktrees(local_path, current.as_deref())?;
+
+    let refs = git_output(
+        local_path,
+        &[
+            "for-each-ref",
+            "--format=%(refname:short)%09%(objectname)%09%(upstream)%09%(upstream:track)",
+            "refs/heads",
+        ],
+    )?;
+
+    let mut report = BranchSyncReport::default();
+    for line in refs.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [branch, sha, upstream, track] = fields[..] else {
+            continue;
+        };
+        if upstream.is_empty() {
+            continue;
+        }
+        if track == "[gone]" {
+            report.gone.push(branch.to_string());
+            continue;
+        }
+
+        if Some(branch) == current.as_deref() {
+            match update_current_branch_with_writer(local_path, UpdateMode::Pull, false, writer)? {
+                PullOutcome::FastForwarded { commits, .. } => {
+                    report.moved.push((branch.to_string(), commits))
+                }
+                PullOutcome::Diverged { .. } => report.diverged.push(branch.to_string()),
+                PullOutcome::Dirty => report.skipped.push((
+                    branch.to_string(),
+                    "checked out with uncommitted changes".to_string(),
+                )),
+                _ => {}
+            }
+            continue;
+        }
+
+        if elsewhere.iter().any(|b| b == branch) {
+            report.skipped.push((
+                branch.to_string(),
+                "checked out in another worktree".to_string(),
+            ));
+            continue;
+        }
+
+        let (ahead, behind) = ahead_behind(local_path, sha, upstream)?;
+        if behind == 0 {
+            continue;
+        }
+        if ahead > 0 {
+            report.diverged.push(branch.to_string());
+            continue;
+        }
+
+        let new_sha = git_output(local_path, &["rev-parse", upstream])?;
+        let refname = format!("refs/heads/{}", branch);
+        git_output(
+            local_path,
+            &[
+                "update-ref",
+                "-m",
+                "git-mirror: fast-forward",
+                &refname,
+                &new_sha,
+                sha,
+            ],
+        )?;
+        report.moved.push((branch.to_string(), behind));
+    }
+
+    Ok(report)
+}
+
+/// Why a local branch is a candidate for deletion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruneReason {
+    /// The upstream branch was deleted on the remote
+    UpstreamGone,
+    /// Every commit is already in the default branch
+    Merged,
+}
+
+impl std::fmt::Display for PruneReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PruneReason::UpstreamGone => write!(f, "upstream gone"),
+            PruneReason::Merged => write!(f, "merged"),
+        }
+    }
+}
+
+/// Work that only exists in a local repository and would be lost with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsavedWork {
+    /// Modified, staged or untracked paths
+    Uncommitted(usize),
+    Stashes(usize),
+    /// Commits not reachable from any remote-tracking branch
+    Unpushed(usize),
+    /// Linked worktrees that would be orphaned
+    Worktrees(usize),
+}
+
+impl std::fmt::Display for UnsavedWork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnsavedWork::Uncommitted(n) => write!(f, "{} uncommitted change(s)", n),
+            UnsavedWork::Stashes(n) => write!(f, "{} stash entr(y/ies)", n),
+            UnsavedWork::Unpushed(n) => write!(f, "{} commit(s) not on any remote", n),
+            UnsavedWork::Worktrees(n) => write!(f, "{} linked worktree(s)", n),
+        }
+    }
+}
+
+/// List what would be lost by deleting the repository at `local_path`.
+pub fn unsaved_work(local_path: &Path) -> Result<Vec<UnsavedWork>> {
+    let mut found = Vec::new();
+    let count_lines = |out: String| out.lines().filter(|l| !l.is_empty()).count();
+    if detect_repo(local_path) != Some(RepoKind::Bare) {
+        let changes = count_lines(git_output(local_path, &["status", "--porcelain"])?);
+        if changes > 0 {
+            found.push(UnsavedWork::Uncommitted(changes));
+        }
+        let stashes = count_lines(git_output(local_path, &["stash", "list"])?);
+        if stashes > 0 {
+            found.push(UnsavedWork::Stashes(stashes));
+        }
+    }
+
+    // A detached HEAD can carry commits no branch or tag points at
+    let mut args = vec!["rev-list", "--count", "--branches", "--tags"];
+    if git_succeeds(local_path, &["rev-parse", "--verify", "--quiet", "HEAD"])? {
+        args.push("HEAD");
+    }
+    args.extend(["--not", "--remotes"]);
+    let unpushed: usize = git_output(local_path, &args)?.parse().unwrap_or(0);
+    if unpushed > 0 {
+        found.push(UnsavedWork::Unpushed(unpushed));
+    }
+
+    let worktrees = git_output(local_path, &["worktree", "list", "--porcelain"])?
+        .lines()
+        .filter(|l| l.starts_with("worktree "))
+        .count();
+    if worktrees > 1 {
+        found.push(UnsavedWork::Worktrees(worktrees - 1));
+    }
+    Ok(found)
+}
+
+/// A summary of a repository's state for listings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RepoStatus {
+    /// The checked-out branch, `None` on a detached HEAD or in a bare repository
+    pub branch: Option<String>,
+    /// Modified, staged or untracked paths exist
+    pub dirty: bool,
+    /// Commits ahead of and behind the upstream, when there is one
+    pub ahead_behind: Option<(usize, usize)>,
+    /// When the repository was last fetched (`FETCH_HEAD`'s modification time)
+    pub last_fetch: Option<SystemTime>,
+}
+
+/// Summarize the branch, working tree and upstream state of a repository.
+pub fn status(local_path: &Path) -> Result<RepoStatus> {
+    let bare = detect_repo(local_path) == Some(RepoKind::Bare);
+    let branch = git_output(local_path, &["symbolic-ref", "--quiet", "--short", "HEAD"]).ok();
+    let dirty = !bare && !git_output(local_path, &["status", "--porcelain"])?.is_empty();
+    let ahead_behind = if bare
+        || !git_succeeds(
+            local_path,
+            &["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"],
+        )? {
+        None
+    } else {
+        Some(ahead_behind(local_path, "HEAD", "@{u}")?)
+    };
+    // Linked worktrees share FETCH_HEAD with their repository
+    let common_dir = PathBuf::from(git_output(local_path, &["rev-parse", "--git-common-dir"])?);
+    let last_fetch = fs::metadata(local_path.join(common_dir).join("FETCH_HEAD"))
+        .and_then(|m| m.modified())
+        .ok();
+    Ok(RepoStatus {
+        branch: branch.filter(|_| !bare),
+        dirty,
+        ahead_behind,
+        last_fetch,
+    })
+}
+
+/// Re-link a repository and its linked worktrees after either was moved.
+pub fn repair_worktrees(local_path: &Path) -> Result<()> {
+    git_output(local_path, &["worktree", "repair"]).map(|_| ())
+}
+
+/// Return the ref of the remote's default branch (e.g. `origin/main`), falling
+/// back to a local `main` or `master` branch.
+pub fn default_branch_ref(local_path: &Path) -> Result<String> {
+    if let Ok(head) = git_output(
+        local_path,
+        &[
+            "symbolic-ref",
+            "--quiet",
+            "--short",
+            "refs/remotes/origin/HEAD",
+        ],
+    ) {
+        return Ok(head);
+    }
+    for candidate in ["origin/main", "origin/master", "main", "master"] {
+        if git_succeeds(local_path, &["rev-parse", "--verify", "--quiet", candidate])? {
+            return Ok(candidate.to_string());
+        }
+    }
+    anyhow::bail!(
+        "could not determine the default branch of {}",
+        local_path.display()
+    )
+}
+
+/// List local branches whose upstream is gone or which are fully merged into
+/// the default branch. The current branch, branches checked out in other
+/// worktrees and the default branch itself are never included.
+pub fn prunable_branches(local_path: &Path) -> Result<Vec<(String, PruneReason)>> {
+    let default_ref = default_branch_ref(local_path)?;
+    let default_name = default_ref
+        .strip_prefix("origin/")
+        .unwrap_or(&default_ref)
+        .to_string();
+    let current = git_output(local_path, &["symbolic-ref", "--quiet", "--short", "HEAD"]).ok();
+    let elsewhere = branches_in_other_worktrees(local_path, current.as_deref())?;
+
+    let merged_listing = git_output(
+        local_path,
+        &[
+            "branch",
+            "--format=%(refname:short)",
+            "--merged",
+            &default_ref,
+        ],
+    )?;
+    let merged: Vec<&str> = merged_listing.lines().collect();
+    let default_tip = git_output(local_path, &["rev-parse", &default_ref])?;
+
+    let refs = git_output(
+        local_path,
+        &[
+            "for-each-ref",
+            "--format=%(refname:short)%09%(upstream)%09%(upstream:track)%09%(objectname)",
+            "refs/heads",
+        ],
+    )?;
+
+    let mut candidates = Vec::new();
+    for line in refs.lines() {
+        let mut fields = line.split('\t');
+        let branch = fields.next().unwrap_or_default();
+        let upstream = fields.next().unwrap_or_default();
+        let track = fields.next().unwrap_or_default();
+        let tip = fields.next().unwrap_or_default();
+        if branch == default_name
+            || Some(branch) == current.as_deref()
+            || elsewhere.iter().any(|b| b == branch)
+        {
+            continue;
+        }
+        // A branch just created from the default branch is "merged" too, but
+        // it's where someone is about to start work
+        let just_started = tip == default_tip && upstream.is_empty();
+        if track == "[gone]" {
+            candidates.push((branch.to_string(), PruneReason::UpstreamGone));
+        } else if merged.contains(&branch) && !just_started {
+            candidates.push((branch.to_string(), PruneReason::Merged));
+        }
+    }
+    Ok(candidates)
+}
+
+/// Delete a local branch. Branches whose upstream is gone may hold unmerged
+/// commits, so callers must have confirmed the deletion first.
+pub fn delete_branch_with_writer<W: Write + ?Sized>(
+    local_path: &Path,
+    branch: &str,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<()> {
+    if dry_run {
+        writeln!(
+            writer,
+            "Dry run: git -C {} branch -D {}",
+            local_path.display(),
+            branch
+        )?;
+        return Ok(());
+    }
+    git_output(local_path, &["branch", "-D", branch])?;
+    Ok(())
+}
+
+/// Return the URL of the `origin` remote, or `None` if there is no origin.
+pub fn origin_url(local_path: &Path) -> Result<Option<String>> {
+    if !git_succeeds(local_path, &["remote", "get-url", "origin"])? {
+        return Ok(None);
+    }
+    Ok(Some(git_output(
+        local_path,
+        &["remote", "get-url", "origin"],
+    )?))
+}
+
+/// Names and fetch URLs of all remotes.
+pub fn remotes(local_path: &Path) -> Result<Vec<(String, String)>> {
+    let listing = git_output(local_path, &["remote", "-v"])?;
+    Ok(listing
+        .lines()
+        .filter_map(|line| line.strip_suffix(" (fetch)")?.split_once('\t'))
+        .map(|(name, url)| (name.to_string(), url.to_string()))
+        .collect())
+}
+
+/// Add `url` as an extra remote called `name`.
+pub fn add_remote_with_writer<W: Write + ?Sized>(
+    local_path: &Path,
+    name: &str,
+    url: &str,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<()> {
+    if dry_run {
+        writeln!(
+            writer,
+            "Dry run: git -C {} remote add {} {}",
+            local_path.display(),
+            name,
+            url
+        )?;
+        return Ok(());
+    }
+    git_output(local_path, &["remote", "add", name, url])?;
+    Ok(())
+}
+
+/// Point `origin` at `url`, creating the remote if it doesn't exist.
+pub fn set_origin_with_writer<W: Write + ?Sized>(
+    local_path: &Path,
+    url: &str,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<()> {
+    let action = if origin_url(local_path)?.is_some() {
+        "set-url"
+    } else {
+        "add"
+    };
+    if dry_run {
+        writeln!(
+            writer,
+            "Dry run: git -C {} remote {} origin {}",
+            local_path.display(),
+            action,
+            url
+        )?;
+        return Ok(());
+    }
+    git_output(local_path, &["remote", action, "origin", url])?;
+    Ok(())
+}
+
+/// A revision to check out after cloning from a web link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckoutTarget {
+    /// A branch on origin, checked out as a local tracking branch
+    Branch(String),
+    /// A tag or commit, checked out with a detached HEAD
+    Detached(String),
+}
+
+impl std::fmt::Display for CheckoutTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckoutTarget::Branch(b) => write!(f, "branch {}", b),
+            CheckoutTarget::Detached(rev) => write!(f, "{} (detached)", rev),
+        }
+    }
+}
+
+/// Split `rest` (a ref followed by a path, as found in web URLs) into the
+/// longest branch or tag of origin it starts with and the remaining path. If
+/// none matches, the first segment is tried as a commit.
+pub fn resolve_web_ref(
+    local_path: &Path,
+    rest: &[String],
+) -> Result<(CheckoutTarget, Vec<String>)> {
+    let branches = git_output(
+        local_path,
+        &[
+            "for-each-ref",
+            "--format=%(refname:strip=3)",
+            "refs/remotes/origin",
+        ],
+    )?;
+    let tags = git_output(
+        local_path,
+        &["for-each-ref", "--format=%(refname:strip=2)", "refs/tags"],
+    )?;
+    let branches: Vec<&str> = branches.lines().filter(|b| *b != "HEAD").collect();
+    let tags: Vec<&str> = tags.lines().collect();
+
+    for len in (1..=rest.len()).rev() {
+        let candidate = rest[..len].join("/");
+        let path = rest[len..].to_vec();
+        if branches.contains(&candidate.as_str()) {
+            return Ok((CheckoutTarget::Branch(candidate), path));
+        }
+        if tags.contains(&candidate.as_str()) {
+            return Ok((CheckoutTarget::Detached(candidate), path));
+        }
+    }
+
+    if let Some(first) = rest.first() {
+        let commit = format!("{}^{{commit}}", first);
+        if git_succeeds(local_path, &["rev-parse", "--verify", "--quiet", &commit])? {
+            return Ok((CheckoutTarget::Detached(first.clone()), rest[1..].to_vec()));
+        }
+    }
+    anyhow::bail!(
+        "no branch, tag or commit of origin matches {}",
+        rest.join("/")
+    )
+}
+
+/// Check out `target`, creating a local tracking branch for a remote branch
+/// that has none yet.
+pub fn checkout_with_writer<W: Write + ?Sized>(
+    local_path: &Path,
+    target: &CheckoutTarget,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<()> {
+    let args: Vec<String> = match target {
+        CheckoutTarget::Branch(branch) => {
+            let current =
+                git_output(local_path, &["symbolic-ref", "--quiet", "--short", "HEAD"]).ok();
+            if current.as_deref() == Some(branch.as_str()) {
+                writeln!(writer, "Already on {}", branch)?;
+                return Ok(());
+            }
+            let local_ref = format!("refs/heads/{}", branch);
+            if git_succeeds(
+                local_path,
+                &["rev-parse", "--verify", "--quiet", &local_ref],
+            )? {
+                vec!["checkout".into(), "-q".into(), branch.clone()]
+            } else {
+                vec![
+                    "checkout".into(),
+                    "-q".into(),
+                    "--track".into(),
+                    "-b".into(),
+                    branch.clone(),
+                    format!("origin/{}", branch),
+                ]
+            }
+        }
+        CheckoutTarget::Detached(rev) => {
+            vec![
+                "checkout".into(),
+                "-q".into(),
+                "--detach".into(),
+                rev.clone(),
+            ]
+        }
+    };
+
+    if dry_run {
+        writeln!(
+            writer,
+            "Dry run: git -C {} {}",
+            local_path.display(),
+            args.join(" ")
+        )?;
+        return Ok(());
+    }
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    git_output(local_path, &args)?;
+    writeln!(writer, "Checked out {}", target)?;
+    Ok(())
+}
+
+/// Open the given local path in VS Code using the `code` command-line tool.
+/// If `dry_run` is true, print the command that would be executed instead of running it.
+pub fn open_in_vscode_with_writer<W: Write + ?Sized>(
+    local_path: &Path,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<()> {
+    if dry_run {
+        writeln!(writer, "Dry run: open VS Code at {}", local_path.display())?;
+        return Ok(());
+    }
+
+    let code = which("code").context("`code` executable not found in PATH")?;
+
+    let status = Command::new(code)
         .arg(local_path)
         .status()
-        .context("failed to spawn git clone")?;
+        .context("failed to spawn code")?;
 
     if !status.success() {
-        anyhow::bail!("git clone failed with status: {}", status);
+        anyhow::bail!("`code` failed with status: {}", status);
     }
 
-    writeln!(
-        writer,
-        "Repository cloned successfully to {}",
-        local_path.display()
-    )?;
+    writeln!(writer, "Opened VS Code at {}", local_path.display())?;
     Ok(())
 }
 
-pub fn clone_repo(repo: &str, local_path: &Path, dry_run: bool) -> Result<()> {
-    let mut stdout = std::io::stdout();
-    clone_repo_with_writer(repo, local_path, dry_run, &mut stdout)
-}
+/// Fetch a pull/merge request's `git_ref` from origin into the local `branch`
+/// and check it out: in the repository itself, or in a linked worktree at
+/// `worktree` (created on first use). An existing branch is only
+/// fast-forwarded; a force-pushed request is reported rather than reset.
+/// Returns the directory the request is checked out in.
+pub fn checkout_pull_request_with_writer<W: Write + ?Sized>(
+    local_path: &Path,
+    git_ref: &str,
+    branch: &str,
+    worktree: Option<&Path>,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<PathBuf> {
+    let checkout_dir = worktree.unwrap_or(local_path).to_path_buf();
+    if dry_run {
+        writeln!(
+            writer,
+            "Dry run: git -C {} fetch origin {}",
+            local_path.display(),
+            git_ref
+        )?;
+        match worktree {
+            Some(dir) => writeln!(
+                writer,
+                "Dry run: git -C {} worktree add {} {}",
+                local_path.display(),
+                dir.display(),
+                branch
+            )?,
+            None => writeln!(
+                writer,
+                "Dry run: git -C {} checkout {}",
+                local_path.display(),
+                branch
+            )?,
+        }
+        return Ok(checkout_dir);
+    }
 
-/// Return true if the given local path appears to be an existing git repository
-pub fn repo_exists(local_path: &Path) -> bool {
-    local_path.exists() && local_path.join(".git").is_dir()
-}
+    git_output(local_path, &["fetch", "-q", "origin", git_ref])?;
+    let head = git_output(local_path, &["rev-parse", "FETCH_HEAD"])?;
+    let local_ref = format!("refs/heads/{}", branch);
+    let existed = git_succeeds(
+        local_path,
+        &["rev-parse", "--verify", "--quiet", &local_ref],
+    )?;
+    if !existed {
+        git_output(local_path, &["branch", "-q", branch, &head])?;
+    }
 
-/// Build the commands to show when a repo already exists locally.
-/// If `print_cd` is true, return only the `cd` command, otherwise return `cd` + `git -C ... pull --ff-only`.
-#[cfg(test)]
-fn existing_repo_commands(local_path: &Path, print_cd: bool) -> String {
-    if print_cd {
-        format!("cd \"{}\"", local_path.display())
-    } else {
-        let cd = format!("cd \"{}\"", local_path.display());
-        let pull = format!("git -C \"{}\" pull --ff-only", local_path.display());
-        format!("{}\n{}", cd, pull)
+    match worktree {
+        Some(dir) if detect_repo(dir).is_some() => {}
+        Some(dir) => {
+            let dir_str = dir.to_string_lossy();
+            git_output(local_path, &["worktree", "add", "-q", &dir_str, branch])?;
+        }
+        None => {
+            let current =
+                git_output(local_path, &["symbolic-ref", "--quiet", "--short", "HEAD"]).ok();
+            if current.as_deref() != Some(branch) {
+                git_output(local_path, &["checkout", "-q", branch])?;
+            }
+        }
     }
-}
 
-/// Fetch an existing repository using `git -C <path> fetch --all --prune`
-pub fn fetch_repo(local_path: &Path, dry_run: bool) -> Result<()> {
-    let git = which("git").context("git executable not found in PATH")?;
+    if existed {
+        let (ahead, behind) = ahead_behind(&checkout_dir, "HEAD", &head)?;
+        if ahead > 0 && behind > 0 {
+            anyhow::bail!(
+                "{} has diverged from the request ({} local, {} new commits; force-pushed?). \
+                 Delete the branch to start over.",
+                branch,
+                ahead,
+                behind
+            );
+        }
+        if behind > 0 {
+            git_output(&checkout_dir, &["merge", "-q", "--ff-only", &head])?;
+            writeln!(writer, "Fast-forwarded {} by {} commit(s)", branch, behind)?;
+        }
+    }
+    writeln!(
+        writer,
+        "Checked out {} in {}",
+        branch,
+        checkout_dir.display()
+    )?;
+    Ok(checkout_dir)
+}
 
+/// Open the repository in VS Code with `file` (relative to the repository)
+/// shown at `line`.
+pub fn open_file_in_vscode_with_writer<W: Write + ?Sized>(
+    local_path: &Path,
+    file: &Path,
+    line: Option<u32>,
+    dry_run: bool,
+    writer: &mut W,
+) -> Result<()> {
+    let goto = format!("{}:{}", local_path.join(file).display(), line.unwrap_or(1));
     if dry_run {
-        println!(
-            "Dry run: git -C {} fetch --all --prune",
-            local_path.display()
-        );
+        writeln!(
+            writer,
+            "Dry run: code {} --goto {}",
+            local_path.display(),
+            goto
+        )?;
         return Ok(());
     }
 
-    let status = Command::new(git)
-        .arg("-C")
+    let code = which("code").context("`code` executable not found in PATH")?;
+    let status = Command::new(code)
         .arg(local_path)
-        .arg("fetch")
-        .arg("--all")
-        .arg("--prune")
+        .arg("--goto")
+        .arg(&goto)
         .status()
-        .context("failed to spawn git fetch")?;
-
+        .context("failed to spawn code")?;
     if !status.success() {
-        anyhow::bail!("git fetch failed with status: {}", status);
+        anyhow::bail!("`code` failed with status: {}", status);
     }
 
+    writeln!(writer, "Opened VS Code at {}", goto)?;
     Ok(())
 }
 
-/// Open the given local path in VS Code using the `code` command-line tool.
-/// If `dry_run` is true, print the command that would be executed instead of running it.
-pub fn open_in_vscode(local_path: &Path, dry_run: bool) -> Result<()> {
-    let mut stdout = std::io::stdout();
-    open_in_vscode_with_writer(local_path, dry_run, &mut stdout)
+/// Where a path inside a working tree sits in its repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoLocation {
+    /// Top of the working tree
+    pub root: PathBuf,
+    /// The path relative to `root` with `/` separators, unless it is `root`
+    pub file: Option<String>,
+    /// The checked-out branch, `None` on a detached HEAD
+    pub branch: Option<String>,
+    pub head: String,
 }
 
-fn open_in_vscode_with_writer<W: Write>(
-    local_path: &Path,
+/// Locate `path` (a directory or file in a working tree) in its repository.
+pub fn locate(path: &Path) -> Result<RepoLocation> {
+    let (dir, name) = if path.is_file() {
+        let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+        (
+            parent.unwrap_or(Path::new(".")),
+            path.file_name().map(|n| n.to_string_lossy().into_owned()),
+        )
+    } else {
+        (path, None)
+    };
+    let root = PathBuf::from(git_output(dir, &["rev-parse", "--show-toplevel"])?);
+    // `--show-prefix` is relative to the top and already uses `/`
+    let prefix = git_output(dir, &["rev-parse", "--show-prefix"])?;
+    let file = format!("{}{}", prefix, name.unwrap_or_default());
+    let file = file.trim_end_matches('/');
+    Ok(RepoLocation {
+        file: (!file.is_empty()).then(|| file.to_string()),
+        branch: git_output(dir, &["symbolic-ref", "--quiet", "--short", "HEAD"]).ok(),
+        head: git_output(dir, &["rev-parse", "HEAD"])?,
+        root,
+    })
+}
+
+/// Open `url` with the system's default handler for it.
+pub fn open_in_browser_with_writer<W: Write + ?Sized>(
+    url: &str,
     dry_run: bool,
     writer: &mut W,
 ) -> Result<()> {
     if dry_run {
-        writeln!(writer, "Dry run: open VS Code at {}", local_path.display())?;
+        writeln!(writer, "Dry run: open {}", url)?;
         return Ok(());
     }
 
-    let code = which("code").context("`code` executable not found in PATH")?;
-
-    let status = Command::new(code)
-        .arg(local_path)
+    // `cmd /c start` would read `&` in the URL as a command separator
+    let (program, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else if cfg!(windows) {
+        ("rundll32", &["url.dll,FileProtocolHandler"])
+    } else {
+        ("xdg-open", &[])
+    };
+    let opener = which(program).with_context(|| format!("`{}` not found in PATH", program))?;
+    let status = Command::new(opener)
+        .args(args)
+        .arg(url)
         .status()
-        .context("failed to spawn code")?;
-
+        .with_context(|| format!("failed to spawn {}", program))?;
     if !status.success() {
-        anyhow::bail!("`code` failed with status: {}", status);
+        anyhow::bail!("`{}` failed with status: {}", program, status);
     }
 
-    writeln!(writer, "Opened VS Code at {}", local_path.display())?;
+    writeln!(writer, "Opened {}", url)?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        clone_repo_with_writer, existing_repo_commands, fetch_repo, open_in_vscode_with_writer,
-        repo_exists,
+        add_remote_with_writer, checkout_pull_request_with_writer, checkout_with_writer,
+        clone_repo_with_writer, delete_branch_with_writer, detect_repo,
+        fast_forward_branches_with_writer, fetch_repo_with_writer, is_linked_worktree, locate,
+        open_file_in_vscode_with_writer, open_in_vscode_with_writer, origin_url, prunable_branches,
+        remotes, repo_exists, repo_layout, resolve_web_ref, set_origin_with_writer, status,
+        unsaved_work, update_current_branch_with_writer, BranchUpdate, CheckoutTarget, PruneReason,
+        PullOutcome, RepoKind, UnsavedWork, UpdateMode,
     };
     use std::env;
     use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+
+    /// Run git in `dir` with a fixed identity, panicking on failure.
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let out = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .expect("spawn git");
+        assert!(
+            out.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8_lossy(&out.stdout).trim().to_string()
+    }
+
+    fn commit_file(dir: &Path, name: &str, contents: &str) {
+        fs::write(dir.join(name), contents).expect("write file");
+        git(dir, &["add", name]);
+        git(dir, &["commit", "-q", "-m", name]);
+    }
+
+    /// Create `<base>/origin.git`, a `seed` clone with one pushed commit on
+    /// `main`, and a `local` clone of it. Returns (base, seed, local).
+    fn setup_remote(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let mut base = env::temp_dir();
+        base.push(name);
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(&base).expect("create base");
+        git(&base, &["init", "-q", "--bare", "-b", "main", "origin.git"]);
+        git(&base, &["clone", "-q", "origin.git", "seed"]);
+        let seed = base.join("seed");
+        git(&seed, &["checkout", "-q", "-b", "main"]);
+        commit_file(&seed, "a.txt", "a");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        git(&base, &["clone", "-q", "origin.git", "local"]);
+        let local = base.join("local");
+        git(&local, &["config", "user.name", "Test"]);
+        git(&local, &["config", "user.email", "test@example.com"]);
+        (base, seed, local)
+    }
 
     #[test]
     fn test_clone_repo_dry_run_captures_output() {
@@ -171,30 +1602,6 @@ mod tests {
         let _ = fs::remove_dir_all(&tmp);
     }
 
-    #[test]
-    fn test_existing_repo_commands_print_cd() {
-        let mut tmp = env::temp_dir();
-        tmp.push("git_mirror_existing_commands_cd_unit");
-        let _ = fs::remove_dir_all(&tmp);
-        fs::create_dir_all(&tmp).expect("create tmp");
-        let s = existing_repo_commands(&tmp, true);
-        assert!(s.starts_with("cd \""));
-        assert!(!s.contains("pull"));
-        let _ = fs::remove_dir_all(&tmp);
-    }
-
-    #[test]
-    fn test_existing_repo_commands_pull() {
-        let mut tmp = env::temp_dir();
-        tmp.push("git_mirror_existing_commands_pull_unit");
-        let _ = fs::remove_dir_all(&tmp);
-        fs::create_dir_all(&tmp).expect("create tmp");
-        let s = existing_repo_commands(&tmp, false);
-        assert!(s.contains("git -C"));
-        assert!(s.contains("pull --ff-only"));
-        let _ = fs::remove_dir_all(&tmp);
-    }
-
     #[test]
     fn test_open_in_vscode_dry_run() {
         let mut tmp = env::temp_dir();
@@ -218,8 +1625,810 @@ mod tests {
         let _ = fs::remove_dir_all(&tmp);
         fs::create_dir_all(&tmp).expect("create tmp");
 
-        let res = fetch_repo(&tmp, true);
+        let mut buf: Vec<u8> = Vec::new();
+        let res = fetch_repo_with_writer(&tmp, true, &mut buf);
         assert!(res.is_ok());
+        let s = String::from_utf8(buf).expect("utf8");
+        assert!(s.contains("Dry run: git -C"));
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn test_update_dry_run() {
+        let tmp = env::temp_dir().join("git_mirror_update_dry_run");
+        let mut buf: Vec<u8> = Vec::new();
+        let res = update_current_branch_with_writer(&tmp, UpdateMode::Pull, true, &mut buf);
+        assert_eq!(res.expect("dry run"), PullOutcome::UpToDate);
+        let s = String::from_utf8(buf).expect("utf8");
+        assert!(s.contains("merge --ff-only"));
+    }
+
+    #[test]
+    fn test_update_pull_fast_forwards() {
+        let (base, seed, local) = setup_remote("git_mirror_update_pull_ff");
+        commit_file(&seed, "b.txt", "b");
+        commit_file(&seed, "c.txt", "c");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        fetch_repo_with_writer(&local, false, &mut io::sink()).expect("fetch");
+
+        let mut buf: Vec<u8> = Vec::new();
+        let outcome = update_current_branch_with_writer(&local, UpdateMode::Pull, false, &mut buf)
+            .expect("update");
+        assert_eq!(
+            outcome,
+            PullOutcome::FastForwarded {
+                branch: "main".to_string(),
+                commits: 2
+            }
+        );
+        assert!(local.join("c.txt").exists());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_update_pull_reports_dirty_and_diverged() {
+        let (base, seed, local) = setup_remote("git_mirror_update_pull_blocked");
+        commit_file(&seed, "b.txt", "b");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        fetch_repo_with_writer(&local, false, &mut io::sink()).expect("fetch");
+
+        fs::write(local.join("a.txt"), "changed").expect("write");
+        let mut buf: Vec<u8> = Vec::new();
+        let outcome = update_current_branch_with_writer(&local, UpdateMode::Pull, false, &mut buf)
+            .expect("update");
+        assert_eq!(outcome, PullOutcome::Dirty);
+
+        git(&local, &["commit", "-q", "-am", "local change"]);
+        let outcome = update_current_branch_with_writer(&local, UpdateMode::Pull, false, &mut buf)
+            .expect("update");
+        assert_eq!(
+            outcome,
+            PullOutcome::Diverged {
+                branch: "main".to_string(),
+                ahead: 1,
+                behind: 1
+            }
+        );
+        assert!(!outcome.updated());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_update_rebase_diverged() {
+        let (base, seed, local) = setup_remote("git_mirror_update_rebase");
+        commit_file(&seed, "b.txt", "b");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        commit_file(&local, "local.txt", "l");
+        fetch_repo_with_writer(&local, false, &mut io::sink()).expect("fetch");
+
+        let mut buf: Vec<u8> = Vec::new();
+        let outcome =
+            update_current_branch_with_writer(&local, UpdateMode::Rebase, false, &mut buf)
+                .expect("update");
+        assert_eq!(
+            outcome,
+            PullOutcome::Rebased {
+                branch: "main".to_string(),
+                commits: 1
+            }
+        );
+        assert!(local.join("b.txt").exists());
+        assert!(local.join("local.txt").exists());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_update_detached_and_no_upstream() {
+        let (base, _seed, local) = setup_remote("git_mirror_update_detached");
+        git(&local, &["checkout", "-q", "-b", "topic"]);
+        let mut buf: Vec<u8> = Vec::new();
+        let outcome = update_current_branch_with_writer(&local, UpdateMode::Pull, false, &mut buf)
+            .expect("update");
+        assert_eq!(
+            outcome,
+            PullOutcome::NoUpstream {
+                branch: "topic".to_string()
+            }
+        );
+
+        git(&local, &["checkout", "-q", "--detach"]);
+        let outcome = update_current_branch_with_writer(&local, UpdateMode::Pull, false, &mut buf)
+            .expect("update");
+        assert_eq!(outcome, PullOutcome::Detached);
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_fast_forward_branches() {
+        let (base, seed, local) = setup_remote("git_mirror_ff_branches");
+        for branch in ["develop", "feature", "old"] {
+            git(&seed, &["checkout", "-q", "-b", branch, "main"]);
+            git(&seed, &["push", "-q", "origin", branch]);
+        }
+        git(&local, &["fetch", "-q"]);
+        for branch in ["develop", "feature", "old"] {
+            let upstream = format!("origin/{}", branch);
+            git(&local, &["branch", "-q", "--track", branch, &upstream]);
+        }
+
+        // develop: upstream strictly ahead; feature: diverged; old: deleted upstream
+        git(&seed, &["checkout", "-q", "develop"]);
+        commit_file(&seed, "d.txt", "d");
+        git(&seed, &["push", "-q", "origin", "develop"]);
+        git(&seed, &["checkout", "-q", "feature"]);
+        commit_file(&seed, "f.txt", "f");
+        git(&seed, &["push", "-q", "origin", "feature"]);
+        git(&local, &["checkout", "-q", "feature"]);
+        commit_file(&local, "mine.txt", "m");
+        git(&local, &["checkout", "-q", "main"]);
+        git(&seed, &["push", "-q", "origin", "--delete", "old"]);
+        git(&seed, &["checkout", "-q", "main"]);
+        commit_file(&seed, "m.txt", "m");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        fetch_repo_with_writer(&local, false, &mut io::sink()).expect("fetch");
+
+        let mut buf: Vec<u8> = Vec::new();
+        let report = fast_forward_branches_with_writer(&local, false, &mut buf).expect("ff");
+        assert_eq!(
+            report.moved,
+            vec![("develop".to_string(), 1), ("main".to_string(), 1)]
+        );
+        assert_eq!(report.diverged, vec!["feature".to_string()]);
+        assert_eq!(report.gone, vec!["old".to_string()]);
+        assert_eq!(
+            git(&local, &["rev-parse", "develop"]),
+            git(&local, &["rev-parse", "origin/develop"])
+        );
+        // main is checked out and was fast-forwarded in the working tree too
+        assert!(local.join("m.txt").exists());
+        assert!(!local.join("d.txt").exists());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_fast_forward_branches_skips_dirty_checkout() {
+        let (base, seed, local) = setup_remote("git_mirror_ff_branches_dirty");
+        commit_file(&seed, "b.txt", "b");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        fetch_repo_with_writer(&local, false, &mut io::sink()).expect("fetch");
+        fs::write(local.join("a.txt"), "changed").expect("write");
+
+        let mut buf: Vec<u8> = Vec::new();
+        let report = fast_forward_branches_with_writer(&local, false, &mut buf).expect("ff");
+        assert!(report.moved.is_empty());
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, "main");
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_prunable_branches() {
+        let (base, seed, local) = setup_remote("git_mirror_prunable_branches");
+        for branch in ["merged", "gone", "active"] {
+            git(&seed, &["checkout", "-q", "-b", branch, "main"]);
+            commit_file(&seed, &format!("{}.txt", branch), branch);
+            git(&seed, &["push", "-q", "origin", branch]);
+        }
+        git(&seed, &["checkout", "-q", "main"]);
+        git(&seed, &["merge", "-q", "--ff-only", "merged"]);
+        git(&seed, &["push", "-q", "origin", "main"]);
+        git(&local, &["fetch", "-q"]);
+        git(&local, &["remote", "set-head", "origin", "main"]);
+        for branch in ["merged", "gone", "active"] {
+            let upstream = format!("origin/{}", branch);
+            git(&local, &["branch", "-q", "--track", branch, &upstream]);
+        }
+        git(&seed, &["push", "-q", "origin", "--delete", "gone"]);
+        git(&local, &["checkout", "-q", "merged"]);
+        fetch_repo_with_writer(&local, false, &mut io::sink()).expect("fetch");
+
+        // the current branch is never a candidate
+        let got = prunable_branches(&local).expect("prunable");
+        assert_eq!(got, vec![("gone".to_string(), PruneReason::UpstreamGone)]);
+
+        git(&local, &["checkout", "-q", "main"]);
+        // a new branch with no commits or upstream of its own is left alone
+        git(
+            &local,
+            &["branch", "-q", "--no-track", "fresh", "origin/main"],
+        );
+        let got = prunable_branches(&local).expect("prunable");
+        assert_eq!(
+            got,
+            vec![
+                ("gone".to_string(), PruneReason::UpstreamGone),
+                ("merged".to_string(), PruneReason::Merged),
+            ]
+        );
+
+        let mut out = Vec::new();
+        delete_branch_with_writer(&local, "gone", true, &mut out).expect("dry run");
+        assert!(String::from_utf8(out).unwrap().contains("branch -D gone"));
+        let branches = git(&local, &["branch", "--format=%(refname:short)"]);
+        assert!(branches.lines().any(|b| b == "gone"));
+
+        delete_branch_with_writer(&local, "gone", false, &mut io::sink()).expect("delete");
+        let branches = git(&local, &["branch", "--format=%(refname:short)"]);
+        assert!(!branches.lines().any(|b| b == "gone"));
+        assert!(branches.lines().any(|b| b == "active"));
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_clone_and_fetch_local_source() {
+        let (base, seed, _local) = setup_remote("git_mirror_local_source");
+        let root = base.join("root");
+        let layout = crate::fs::Layout {
+            full_host: false,
+            include_port: false,
+            local: crate::config::LocalConfig {
+                host: "local".to_string(),
+                strip_prefix: Some(base.clone()),
+            },
+            ..crate::fs::Layout::default()
+        };
+
+        for (source, name) in [
+            (
+                format!("file://{}", base.join("origin.git").display()),
+                "file",
+            ),
+            (base.join("origin.git").display().to_string(), "path"),
+        ] {
+            let root = root.join(name);
+            let target = layout
+                .local_path(root.to_str().unwrap(), &source)
+                .expect("layout");
+            assert_eq!(target, root.join("local").join("origin"));
+
+            clone_repo_with_writer(&source, &target, false, &mut io::sink()).expect("clone");
+            assert_eq!(detect_repo(&target), Some(RepoKind::WorkTree));
+            assert!(target.join("a.txt").exists());
+            let origin = origin_url(&target).expect("origin").expect("has origin");
+            assert!(crate::util::same_repo_url(&origin, &source));
+        }
+
+        commit_file(&seed, "b.txt", "b");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        let target = root.join("file").join("local").join("origin");
+        let summary = fetch_repo_with_writer(&target, false, &mut io::sink()).expect("fetch");
+        assert_eq!(
+            summary.updated,
+            vec![BranchUpdate {
+                branch: "origin/main".to_string(),
+                commits: 1,
+                forced: false,
+            }]
+        );
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_status() {
+        let (base, seed, local) = setup_remote("git_mirror_status");
+        let clean = status(&local).expect("status");
+        assert_eq!(clean.branch.as_deref(), Some("main"));
+        assert!(!clean.dirty);
+        assert_eq!(clean.ahead_behind, Some((0, 0)));
+        assert_eq!(clean.last_fetch, None);
+
+        commit_file(&seed, "b.txt", "b");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        git(&local, &["fetch", "-q"]);
+        commit_file(&local, "c.txt", "c");
+        fs::write(local.join("new.txt"), "x").expect("write");
+        let changed = status(&local).expect("status");
+        assert!(changed.dirty);
+        assert_eq!(changed.ahead_behind, Some((1, 1)));
+        assert!(changed.last_fetch.is_some());
+
+        git(&local, &["checkout", "-q", "--detach"]);
+        let detached = status(&local).expect("status");
+        assert_eq!(detached.branch, None);
+        assert_eq!(detached.ahead_behind, None);
+
+        let bare = status(&base.join("origin.git")).expect("bare status");
+        assert_eq!(bare, Default::default());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_unsaved_work() {
+        let (base, _seed, local) = setup_remote("git_mirror_unsaved_work");
+        assert_eq!(unsaved_work(&local).expect("clean"), vec![]);
+
+        fs::write(local.join("new.txt"), "x").expect("write");
+        assert_eq!(
+            unsaved_work(&local).expect("untracked"),
+            vec![UnsavedWork::Uncommitted(1)]
+        );
+        git(&local, &["add", "new.txt"]);
+        git(&local, &["stash", "-q"]);
+        assert_eq!(
+            unsaved_work(&local).expect("stash"),
+            vec![UnsavedWork::Stashes(1)]
+        );
+        git(&local, &["stash", "drop", "-q"]);
+
+        commit_file(&local, "a.txt", "local only");
+        assert_eq!(
+            unsaved_work(&local).expect("unpushed"),
+            vec![UnsavedWork::Unpushed(1)]
+        );
+        // Commits left behind on a detached HEAD count too
+        git(&local, &["checkout", "-q", "--detach"]);
+        commit_file(&local, "b.txt", "detached");
+        git(&local, &["branch", "-q", "-f", "main", "origin/main"]);
+        assert_eq!(
+            unsaved_work(&local).expect("detached"),
+            vec![UnsavedWork::Unpushed(2)]
+        );
+        git(&local, &["checkout", "-q", "main"]);
+        assert_eq!(unsaved_work(&local).expect("pushed"), vec![]);
+
+        git(
+            &local,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "wt",
+                base.join("wt").to_str().unwrap(),
+            ],
+        );
+        assert_eq!(
+            unsaved_work(&local).expect("worktree"),
+            vec![UnsavedWork::Worktrees(1)]
+        );
+        // A bare repository without remotes is the only copy of its history
+        assert_eq!(
+            unsaved_work(&base.join("origin.git")).expect("bare"),
+            vec![UnsavedWork::Unpushed(1)]
+        );
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_locate() {
+        let (base, _seed, local) = setup_remote("git_mirror_locate");
+        fs::create_dir_all(local.join("src")).expect("create src");
+        commit_file(&local, "src/lib.rs", "fn x() {}");
+        let head = git(&local, &["rev-parse", "HEAD"]);
+
+        let at_root = locate(&local).expect("locate root");
+        assert_eq!(at_root.file, None);
+        assert_eq!(at_root.branch.as_deref(), Some("main"));
+        assert_eq!(at_root.head, head);
+        assert_eq!(
+            at_root.root.canonicalize().expect("canonicalize"),
+            local.canonicalize().expect("canonicalize")
+        );
+
+        let file = locate(&local.join("src").join("lib.rs")).expect("locate file");
+        assert_eq!(file.file.as_deref(), Some("src/lib.rs"));
+        assert_eq!(file.root, at_root.root);
+        let dir = locate(&local.join("src")).expect("locate dir");
+        assert_eq!(dir.file.as_deref(), Some("src"));
+
+        git(&local, &["checkout", "-q", "--detach"]);
+        assert_eq!(locate(&local).expect("locate").branch, None);
+        assert!(locate(&base).is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_resolve_web_ref_and_checkout() {
+        let (base, seed, local) = setup_remote("git_mirror_web_ref");
+        git(&seed, &["checkout", "-q", "-b", "feature/x", "main"]);
+        fs::create_dir_all(seed.join("src")).expect("create src");
+        commit_file(&seed, "src/lib.rs", "fn x() {}");
+        git(&seed, &["push", "-q", "origin", "feature/x"]);
+        git(&seed, &["tag", "v1.0", "main"]);
+        git(&seed, &["push", "-q", "origin", "v1.0"]);
+        git(&local, &["fetch", "-q", "--tags"]);
+
+        let segs = |s: &str| s.split('/').map(str::to_string).collect::<Vec<_>>();
+        let (target, path) = resolve_web_ref(&local, &segs("feature/x/src/lib.rs")).expect("ref");
+        assert_eq!(target, CheckoutTarget::Branch("feature/x".to_string()));
+        assert_eq!(path, segs("src/lib.rs"));
+
+        let (target, path) = resolve_web_ref(&local, &segs("main")).expect("ref");
+        assert_eq!(target, CheckoutTarget::Branch("main".to_string()));
+        assert!(path.is_empty());
+
+        let (target, path) = resolve_web_ref(&local, &segs("v1.0/a.txt")).expect("ref");
+        assert_eq!(target, CheckoutTarget::Detached("v1.0".to_string()));
+        assert_eq!(path, segs("a.txt"));
+
+        let sha = git(&local, &["rev-parse", "HEAD"]);
+        let (target, _) = resolve_web_ref(&local, &segs(&format!("{}/a.txt", sha))).expect("ref");
+        assert_eq!(target, CheckoutTarget::Detached(sha));
+
+        assert!(resolve_web_ref(&local, &segs("nope/a.txt")).is_err());
+
+        let target = CheckoutTarget::Branch("feature/x".to_string());
+        let mut buf: Vec<u8> = Vec::new();
+        checkout_with_writer(&local, &target, true, &mut buf).expect("dry run");
+        assert!(String::from_utf8(buf)
+            .expect("utf8")
+            .contains("--track -b feature/x origin/feature/x"));
+
+        checkout_with_writer(&local, &target, false, &mut io::sink()).expect("checkout");
+        assert_eq!(
+            git(&local, &["symbolic-ref", "--short", "HEAD"]),
+            "feature/x"
+        );
+        assert!(local.join("src/lib.rs").exists());
+        let mut buf: Vec<u8> = Vec::new();
+        checkout_with_writer(&local, &target, false, &mut buf).expect("again");
+        assert!(String::from_utf8(buf).expect("utf8").contains("Already on"));
+
+        let mut buf: Vec<u8> = Vec::new();
+        open_file_in_vscode_with_writer(&local, Path::new("src/lib.rs"), Some(3), true, &mut buf)
+            .expect("dry run");
+        assert!(String::from_utf8(buf).expect("utf8").contains("--goto"));
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_checkout_pull_request() {
+        let (base, seed, local) = setup_remote("git_mirror_pull_request");
+        git(&seed, &["checkout", "-q", "-b", "contrib", "main"]);
+        commit_file(&seed, "pr.txt", "one");
+        git(&seed, &["push", "-q", "origin", "HEAD:refs/pull/7/head"]);
+
+        let mut buf: Vec<u8> = Vec::new();
+        let dir = checkout_pull_request_with_writer(
+            &local,
+            "refs/pull/7/head",
+            "pr/7",
+            None,
+            false,
+            &mut buf,
+        )
+        .expect("checkout");
+        assert_eq!(dir, local);
+        assert_eq!(git(&local, &["symbolic-ref", "--short", "HEAD"]), "pr/7");
+        assert!(local.join("pr.txt").exists());
+
+        // New commits on the request fast-forward the branch
+        commit_file(&seed, "pr2.txt", "two");
+        git(&seed, &["push", "-q", "origin", "HEAD:refs/pull/7/head"]);
+        let mut buf: Vec<u8> = Vec::new();
+        checkout_pull_request_with_writer(
+            &local,
+            "refs/pull/7/head",
+            "pr/7",
+            None,
+            false,
+            &mut buf,
+        )
+        .expect("update");
+        assert!(String::from_utf8(buf)
+            .expect("utf8")
+            .contains("Fast-forwarded pr/7 by 1"));
+        assert!(local.join("pr2.txt").exists());
+
+        // A force-pushed request is reported, not reset
+        git(&seed, &["commit", "-q", "--amend", "-m", "rewritten"]);
+        git(
+            &seed,
+            &["push", "-q", "-f", "origin", "HEAD:refs/pull/7/head"],
+        );
+        let err = checkout_pull_request_with_writer(
+            &local,
+            "refs/pull/7/head",
+            "pr/7",
+            None,
+            false,
+            &mut io::sink(),
+        )
+        .expect_err("diverged");
+        assert!(err.to_string().contains("diverged"));
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_checkout_pull_request_worktree() {
+        let (base, seed, local) = setup_remote("git_mirror_pull_request_worktree");
+        git(&seed, &["checkout", "-q", "-b", "contrib", "main"]);
+        commit_file(&seed, "mr.txt", "one");
+        git(
+            &seed,
+            &["push", "-q", "origin", "HEAD:refs/merge-requests/3/head"],
+        );
+
+        let worktree = base.join("local.mr-3");
+        let mut buf: Vec<u8> = Vec::new();
+        checkout_pull_request_with_writer(
+            &local,
+            "refs/merge-requests/3/head",
+            "mr/3",
+            Some(&worktree),
+            true,
+            &mut buf,
+        )
+        .expect("dry run");
+        assert!(String::from_utf8(buf)
+            .expect("utf8")
+            .contains("worktree add"));
+        assert!(!worktree.exists());
+
+        let dir = checkout_pull_request_with_writer(
+            &local,
+            "refs/merge-requests/3/head",
+            "mr/3",
+            Some(&worktree),
+            false,
+            &mut io::sink(),
+        )
+        .expect("worktree");
+        assert_eq!(dir, worktree);
+        assert_eq!(detect_repo(&worktree), Some(RepoKind::GitFile));
+        assert!(is_linked_worktree(&worktree));
+        assert!(!is_linked_worktree(&local));
+        assert!(worktree.join("mr.txt").exists());
+        // The main checkout stays on its branch
+        assert_eq!(git(&local, &["symbolic-ref", "--short", "HEAD"]), "main");
+
+        // Running again reuses the worktree
+        commit_file(&seed, "mr2.txt", "two");
+        git(
+            &seed,
+            &["push", "-q", "origin", "HEAD:refs/merge-requests/3/head"],
+        );
+        checkout_pull_request_with_writer(
+            &local,
+            "refs/merge-requests/3/head",
+            "mr/3",
+            Some(&worktree),
+            false,
+            &mut io::sink(),
+        )
+        .expect("update worktree");
+        assert!(worktree.join("mr2.txt").exists());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_fetch_summary() {
+        let (base, seed, local) = setup_remote("git_mirror_fetch_summary");
+        for branch in ["doomed", "rewritten"] {
+            git(&seed, &["checkout", "-q", "-b", branch, "main"]);
+            commit_file(&seed, &format!("{}.txt", branch), branch);
+            git(&seed, &["push", "-q", "origin", branch]);
+        }
+        git(&local, &["fetch", "-q"]);
+
+        git(&seed, &["checkout", "-q", "main"]);
+        commit_file(&seed, "b.txt", "b");
+        commit_file(&seed, "c.txt", "c");
+        git(&seed, &["push", "-q", "origin", "main"]);
+        git(&seed, &["push", "-q", "origin", "--delete", "doomed"]);
+        git(&seed, &["checkout", "-q", "rewritten"]);
+        git(&seed, &["commit", "-q", "--amend", "-m", "rewritten again"]);
+        git(&seed, &["push", "-q", "-f", "origin", "rewritten"]);
+        git(&seed, &["checkout", "-q", "-b", "fresh", "main"]);
+        git(&seed, &["push", "-q", "origin", "fresh"]);
+        git(&seed, &["tag", "v1.0", "main"]);
+        git(&seed, &["push", "-q", "origin", "v1.0"]);
+
+        let summary = fetch_repo_with_writer(&local, false, &mut io::sink()).expect("fetch");
+        assert_eq!(
+            summary.updated,
+            vec![
+                BranchUpdate {
+                    branch: "origin/main".to_string(),
+                    commits: 2,
+                    forced: false
+                },
+                BranchUpdate {
+                    branch: "origin/rewritten".to_string(),
+                    commits: 1,
+                    forced: true
+                },
+            ]
+        );
+        assert_eq!(summary.new_branches, vec!["origin/fresh".to_string()]);
+        assert_eq!(summary.deleted_branches, vec!["origin/doomed".to_string()]);
+        assert_eq!(summary.new_tags, vec!["v1.0".to_string()]);
+        assert!(summary.to_string().contains("forced update"));
+
+        let again = fetch_repo_with_writer(&local, false, &mut io::sink()).expect("fetch");
+        assert!(again.is_empty());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_origin_remote_management() {
+        let (base, _seed, local) = setup_remote("git_mirror_origin_remotes");
+        let origin = origin_url(&local).expect("origin").expect("has origin");
+        assert!(origin.ends_with("origin.git"));
+
+        let mut out = Vec::new();
+        add_remote_with_writer(
+            &local,
+            "fork",
+            "git@github.com:fork/repo.git",
+            true,
+            &mut out,
+        )
+        .expect("dry run");
+        assert!(String::from_utf8(out).unwrap().contains("remote add fork"));
+        assert_eq!(remotes(&local).expect("remotes").len(), 1);
+
+        add_remote_with_writer(
+            &local,
+            "fork",
+            "git@github.com:fork/repo.git",
+            false,
+            &mut io::sink(),
+        )
+        .expect("add");
+        assert_eq!(
+            git(&local, &["remote", "get-url", "fork"]),
+            "git@github.com:fork/repo.git"
+        );
+        // a rerun finds the remote the first run added
+        let found = remotes(&local).expect("remotes");
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().any(|(name, url)| name == "fork"
+            && crate::util::same_repo_url(url, "https://github.com/fork/repo")));
+
+        set_origin_with_writer(
+            &local,
+            "git@github.com:owner/repo.git",
+            false,
+            &mut io::sink(),
+        )
+        .expect("set");
+        assert_eq!(
+            origin_url(&local).expect("origin").as_deref(),
+            Some("git@github.com:owner/repo.git")
+        );
+
+        git(&local, &["remote", "remove", "origin"]);
+        assert!(origin_url(&local).expect("origin").is_none());
+        let mut out = Vec::new();
+        set_origin_with_writer(&local, "git@github.com:owner/repo.git", true, &mut out)
+            .expect("dry run");
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("remote add origin"));
+        set_origin_with_writer(
+            &local,
+            "git@github.com:owner/repo.git",
+            false,
+            &mut io::sink(),
+        )
+        .expect("add origin");
+        assert!(origin_url(&local).expect("origin").is_some());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_clone_repo_moves_staging_into_place() {
+        let (base, _seed, _local) = setup_remote("git_mirror_clone_staging");
+        let origin = base.join("origin.git");
+        let target = base.join("mirror").join("repo");
+        // an empty directory at the target is replaced
+        fs::create_dir_all(&target).expect("create target");
+
+        // staging left by a killed run is removed, a running one's is kept
+        let stale = base.join("mirror").join(".repo.git-mirror-4000000000");
+        fs::create_dir_all(stale.join(".git")).expect("create stale staging");
+        let mut running = Command::new("git")
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .expect("spawn git");
+        let live = base
+            .join("mirror")
+            .join(format!(".repo.git-mirror-{}", running.id()));
+        fs::create_dir_all(&live).expect("create live staging");
+
+        let mut buf: Vec<u8> = Vec::new();
+        clone_repo_with_writer(origin.to_str().unwrap(), &target, false, &mut buf).expect("clone");
+        assert!(!stale.exists());
+        assert!(live.exists());
+        let _ = running.kill();
+        let _ = running.wait();
+        fs::remove_dir_all(&live).expect("remove live staging");
+        assert!(target.join(".git").is_dir());
+        assert!(target.join("a.txt").exists());
+        let leftovers: Vec<_> = fs::read_dir(base.join("mirror"))
+            .expect("read dir")
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name() != "repo")
+            .collect();
+        assert!(leftovers.is_empty());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_clone_repo_failure_leaves_nothing_behind() {
+        let mut base = env::temp_dir();
+        base.push("git_mirror_clone_failure");
+        let _ = fs::remove_dir_all(&base);
+        let target = base.join("repo");
+        let missing = base.join("does-not-exist.git");
+
+        let mut buf: Vec<u8> = Vec::new();
+        let res = clone_repo_with_writer(missing.to_str().unwrap(), &target, false, &mut buf);
+        assert!(res.is_err());
+        assert!(!target.exists());
+        let entries = fs::read_dir(&base).expect("read dir").count();
+        assert_eq!(entries, 0);
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_detect_repo_layouts() {
+        let (base, _seed, local) = setup_remote("git_mirror_detect_layouts");
+        assert_eq!(detect_repo(&local), Some(RepoKind::WorkTree));
+
+        // linked worktree: `.git` is a gitfile
+        let linked = base.join("linked");
+        git(
+            &local,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "wt",
+                linked.to_str().unwrap(),
+            ],
+        );
+        assert!(linked.join(".git").is_file());
+        assert_eq!(detect_repo(&linked), Some(RepoKind::GitFile));
+
+        assert_eq!(detect_repo(&base.join("origin.git")), Some(RepoKind::Bare));
+
+        // directories inside a repository are not repositories themselves
+        let nested = local.join("sub");
+        fs::create_dir_all(&nested).expect("create nested");
+        assert_eq!(detect_repo(&nested), None);
+        assert!(!repo_exists(&base));
+
+        // The filesystem checks alone recognise the same layouts
+        assert_eq!(repo_layout(&local), Some(RepoKind::WorkTree));
+        assert_eq!(repo_layout(&linked), Some(RepoKind::GitFile));
+        assert_eq!(repo_layout(&base.join("origin.git")), Some(RepoKind::Bare));
+        assert_eq!(repo_layout(&nested), None);
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_detect_repo_submodule_gitfile() {
+        let (base, _seed, local) = setup_remote("git_mirror_detect_submodule");
+        let origin = base.join("origin.git");
+        git(
+            &local,
+            &[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                "-q",
+                origin.to_str().unwrap(),
+                "vendor/dep",
+            ],
+        );
+        let sub = local.join("vendor/dep");
+        assert_eq!(detect_repo(&sub), Some(RepoKind::GitFile));
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_detect_repo_broken_gitfile() {
+        let mut tmp = env::temp_dir();
+        tmp.push("git_mirror_detect_broken_gitfile");
+        let _ = fs::remove_dir_all(&tmp);
+        fs::create_dir_all(&tmp).expect("create tmp");
+        fs::write(tmp.join(".git"), "gitdir: ../missing/.git\n").expect("write gitfile");
+        assert_eq!(detect_repo(&tmp), None);
         let _ = fs::remove_dir_all(&tmp);
     }
 }
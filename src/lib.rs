@@ -0,0 +1,46 @@
+//! Mirror Git repositories into a predictable directory tree, e.g.
+//! `git@github.com:owner/repo.git` into `~/Projects/github/owner/repo`.
+//!
+//! [`RepoSpec`] parses repository URLs, [`Layout`] maps them to directories
+//! below a root, and [`Mirror`] clones, fetches and inspects repositories
+//! there. The `git-mirror` command line tool is built on this crate.
+//!
+//! ```no_run
+//! use git_mirror::{Layout, Mirror, Synced};
+//!
+//! let mirror = Mirror::new("~/Projects", Layout::default());
+//! let synced = mirror.sync("https://github.com/owner/repo.git")?;
+//! if let Synced::Fetched(_, summary) = &synced {
+//!     println!("fetched: {}", summary);
+//! }
+//! println!("mirrored at {}", synced.path().display());
+//! # Ok::<(), git_mirror::Error>(())
+//! ```
+
+// Parts of the internals only the command line tool uses
+#![cfg_attr(not(feature = "cli"), allow(dead_code))]
+
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cli;
+#[cfg(feature = "cli")]
+mod completions;
+mod config;
+#[cfg(feature = "cli")]
+mod doctor;
+mod error;
+mod forge;
+mod fs;
+mod git;
+mod mirror;
+mod spec;
+mod ssh_config;
+#[cfg(feature = "cli")]
+mod tui;
+mod util;
+
+pub use error::{Error, Result};
+pub use fs::Layout;
+pub use git::{BranchUpdate, FetchSummary, RepoStatus};
+pub use mirror::{Mirror, Synced};
+pub use spec::{RepoSpec, Scheme};
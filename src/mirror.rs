@@ -0,0 +1,235 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::fs::{self, Layout, TargetState};
+use crate::git::{self, FetchSummary, RepoStatus};
+use crate::spec::RepoSpec;
+
+/// Repositories mirrored below a root directory according to a [`Layout`].
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    root: String,
+    layout: Layout,
+    dry_run: bool,
+    prompt: bool,
+}
+
+/// What [`Mirror::sync`] did to bring a repository up to date.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Synced {
+    Cloned(PathBuf),
+    Fetched(PathBuf, FetchSummary),
+}
+
+impl Mirror {
+    /// A mirror below `root`, which may start with `~`.
+    pub fn new(root: impl Into<String>, layout: Layout) -> Mirror {
+        Mirror {
+            root: root.into(),
+            layout,
+            dry_run: false,
+            prompt: true,
+        }
+    }
+
+    /// Describe clones and fetches instead of running them.
+    pub fn dry_run(mut self, dry_run: bool) -> Mirror {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Whether fetches may prompt for credentials or an SSH passphrase.
+    /// Turn it off where nobody can answer, and they fail instead.
+    pub fn prompt(mut self, prompt: bool) -> Mirror {
+        self.prompt = prompt;
+        self
+    }
+
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// The directory `repo` is mirrored to, whether or not it exists yet.
+    pub fn local_path(&self, repo: &str) -> Result<PathBuf> {
+        self.layout.local_path(&self.root, repo)
+    }
+
+    /// Every repository below the root.
+    pub fn repos(&self) -> Result<Vec<PathBuf>> {
+        let root = fs::expand_root(&self.root)?;
+        Ok(if root.is_dir() {
+            fs::find_repos(&root)
+        } else {
+            Vec::new()
+        })
+    }
+
+    /// Clone `repo` into its directory, which must be missing or empty.
+    pub fn clone_repo(&self, repo: &str) -> Result<PathBuf> {
+        self.clone_repo_with_writer(repo, &mut io::sink())
+    }
+
+    /// Like [`Mirror::clone_repo`], reporting progress to `writer`.
+    pub fn clone_repo_with_writer<W: Write>(&self, repo: &str, writer: &mut W) -> Result<PathBuf> {
+        let spec = RepoSpec::parse(repo)?;
+        let local = self.local_path(repo)?;
+        // A dry run leaves whatever is in the way where it is
+        match fs::inspect_target(&local)? {
+            TargetState::NonEmptyDir(_) | TargetState::NotADirectory if !self.dry_run => {
+                return Err(Error::TargetExists { path: local });
+            }
+            _ => {}
+        }
+        git::clone_repo_with_writer(&spec.clone_url(), &local, self.dry_run, writer)?;
+        Ok(local)
+    }
+
+    /// Fetch all remotes of the mirrored `repo` (a URL, or a directory inside
+    /// the root) and summarize what changed.
+    pub fn fetch(&self, repo: &str) -> Result<FetchSummary> {
+        self.fetch_with_writer(repo, &mut io::sink())
+    }
+
+    /// Like [`Mirror::fetch`], reporting progress to `writer`.
+    pub fn fetch_with_writer<W: Write>(&self, repo: &str, writer: &mut W) -> Result<FetchSummary> {
+        let local = self.existing(repo)?;
+        self.fetch_local(&local, writer)
+    }
+
+    /// The branch, working tree and upstream state of the mirrored `repo`.
+    pub fn status(&self, repo: &str) -> Result<RepoStatus> {
+        let local = self.existing(repo)?;
+        Ok(git::status(&local)?)
+    }
+
+    /// Clone `repo` if it isn't mirrored yet, otherwise fetch it.
+    pub fn sync(&self, repo: &str) -> Result<Synced> {
+        let local = self.local_path(repo)?;
+        if git::repo_exists(&local) {
+            let summary = self.fetch_local(&local, &mut io::sink())?;
+            Ok(Synced::Fetched(local, summary))
+        } else {
+            self.clone_repo(repo).map(Synced::Cloned)
+        }
+    }
+
+    fn fetch_local<W: Write>(&self, local: &Path, writer: &mut W) -> Result<FetchSummary> {
+        let summary = if self.prompt {
+            git::fetch_repo_with_writer(local, self.dry_run, writer)?
+        } else {
+            git::fetch_repo_batch_with_writer(local, self.dry_run, writer)?
+        };
+        Ok(summary)
+    }
+
+    /// The directory of `repo`, which must already be mirrored. A directory
+    /// inside the root is taken as is.
+    fn existing(&self, repo: &str) -> Result<PathBuf> {
+        let local = fs::resolve_mirror(&self.root, repo, &self.layout)?;
+        if !git::repo_exists(&local) {
+            return Err(Error::NotMirrored { path: local });
+        }
+        Ok(local)
+    }
+}
+
+impl Synced {
+    /// The repository's directory.
+    pub fn path(&self) -> &Path {
+        match self {
+            Synced::Cloned(path) | Synced::Fetched(path, _) => path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mirror, Synced};
+    use crate::error::Error;
+    use crate::fs::Layout;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .status()
+            .expect("run git");
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn test_sync_clones_then_fetches() {
+        let mut base = env::temp_dir();
+        base.push("git_mirror_mirror_sync");
+        let _ = fs::remove_dir_all(&base);
+        let source = base.join("src").join("team").join("app");
+        fs::create_dir_all(&source).expect("create source");
+        git(&source, &["init", "-q", "-b", "main"]);
+        fs::write(source.join("a.txt"), "a").expect("write");
+        git(&source, &["add", "."]);
+        git(
+            &source,
+            &[
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@e",
+                "commit",
+                "-q",
+                "-m",
+                "a",
+            ],
+        );
+
+        let mirror = Mirror::new(base.join("root").to_str().unwrap(), Layout::default());
+        let repo = source.to_str().unwrap();
+        assert!(matches!(
+            mirror.status(repo),
+            Err(Error::NotMirrored { .. })
+        ));
+
+        let local = match mirror.sync(repo).expect("clone") {
+            Synced::Cloned(local) => local,
+            other => panic!("expected a clone, got {:?}", other),
+        };
+        assert_eq!(local, mirror.local_path(repo).expect("path"));
+        assert_eq!(mirror.repos().expect("repos"), vec![local.clone()]);
+        assert_eq!(
+            mirror.status(repo).expect("status").branch.as_deref(),
+            Some("main")
+        );
+        assert!(mirror.status(local.to_str().unwrap()).is_ok());
+        let fetched = mirror.sync(repo).expect("fetch");
+        assert!(matches!(fetched, Synced::Fetched(..)));
+        assert_eq!(fetched.path(), local);
+        assert!(matches!(
+            mirror.clone_repo(repo),
+            Err(Error::TargetExists { .. })
+        ));
+        assert!(matches!(
+            mirror.local_path("not a url"),
+            Err(Error::InvalidUrl(_))
+        ));
+
+        let other = base.join("root").join("elsewhere");
+        let dry = Mirror::new(other.to_str().unwrap(), Layout::default()).dry_run(true);
+        let mut out = Vec::new();
+        dry.clone_repo_with_writer(repo, &mut out).expect("dry run");
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("Dry run: git clone"));
+        assert!(!other.exists());
+        let _ = fs::remove_dir_all(&base);
+    }
+}
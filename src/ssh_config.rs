@@ -0,0 +1,338 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// ssh gives up on Include chains deeper than this; so do we.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// The parts of the user's `~/.ssh/config` git-mirror cares about: which real
+/// host an alias such as `github-work` connects to.
+#[derive(Debug, Clone, Default)]
+pub struct SshConfig {
+    entries: Vec<Entry>,
+}
+
+/// One `keyword value` line with the `Host` patterns in effect where it
+/// appeared. `None` means the line can never apply (inside a `Match` block).
+#[derive(Debug, Clone)]
+struct Entry {
+    patterns: Option<Vec<String>>,
+    key: String,
+    value: String,
+}
+
+impl SshConfig {
+    /// Load `~/.ssh/config`. A missing or unreadable file yields an empty
+    /// config, so aliases simply aren't resolved.
+    pub fn load() -> SshConfig {
+        let Some(ssh_dir) = dirs::home_dir().map(|h| h.join(".ssh")) else {
+            return SshConfig::default();
+        };
+        SshConfig::load_from(&ssh_dir.join("config"), &ssh_dir)
+    }
+
+    /// Load the config at `path`, resolving relative `Include` paths against
+    /// `ssh_dir` as ssh does for user configs.
+    pub fn load_from(path: &Path, ssh_dir: &Path) -> SshConfig {
+        let mut config = SshConfig::default();
+        let global = Some(vec!["*".to_string()]);
+        config.read_file(path, ssh_dir, &global, 0);
+        config
+    }
+
+    fn read_file(
+        &mut self,
+        path: &Path,
+        ssh_dir: &Path,
+        patterns: &Option<Vec<String>>,
+        depth: usize,
+    ) {
+        if depth > MAX_INCLUDE_DEPTH {
+            return;
+        }
+        if let Ok(text) = fs::read_to_string(path) {
+            self.parse(&text, ssh_dir, patterns.clone(), depth);
+        }
+    }
+
+    /// Parse config text. Lines before the first `Host` or `Match` keep the
+    /// patterns of the enclosing block (all hosts at the top level).
+    fn parse(
+        &mut self,
+        text: &str,
+        ssh_dir: &Path,
+        mut patterns: Option<Vec<String>>,
+        depth: usize,
+    ) {
+        for line in text.lines() {
+            let Some((key, value)) = split_line(line) else {
+                continue;
+            };
+            match key.as_str() {
+                "host" => {
+                    patterns = Some(value.split_whitespace().map(str::to_string).collect());
+                }
+                // Match criteria (exec, user, ...) aren't evaluated; ignore the block
+                "match" => patterns = None,
+                "include" => {
+                    for file in value
+                        .split_whitespace()
+                        .flat_map(|p| expand_include(p, ssh_dir))
+                    {
+                        self.read_file(&file, ssh_dir, &patterns, depth + 1);
+                    }
+                }
+                _ => self.entries.push(Entry {
+                    patterns: patterns.clone(),
+                    key,
+                    value,
+                }),
+            }
+        }
+    }
+
+    /// The first value of `key` that applies to `host`, as ssh picks it.
+    fn get(&self, host: &str, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .filter(|e| e.key == key)
+            .find(|e| e.patterns.as_ref().is_some_and(|p| host_matches(host, p)))
+            .map(|e| e.value.as_str())
+    }
+
+    /// The real host name `host` connects to, if the config sets `HostName`
+    /// for it. `%h` in the value stands for the alias itself.
+    pub fn hostname(&self, host: &str) -> Option<String> {
+        let value = self.get(host, "hostname")?;
+        let resolved = value.replace("%h", host).replace("%%", "%");
+        (resolved != host).then_some(resolved)
+    }
+}
+
+/// Split a config line into a lowercased keyword and its arguments, accepting
+/// both `Key value` and `Key=value`. Comments and blank lines yield `None`.
+fn split_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let (key, rest) = line.split_at(end);
+    let value = rest.trim_start().strip_prefix('=').unwrap_or(rest).trim();
+    let value = value.trim_matches('"');
+    Some((key.to_ascii_lowercase(), value.to_string()))
+}
+
+/// A host matches a `Host` line when it matches one of its patterns and none
+/// of its negated (`!pattern`) ones.
+fn host_matches(host: &str, patterns: &[String]) -> bool {
+    let host = host.to_ascii_lowercase();
+    let mut matched = false;
+    for pattern in patterns {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard_match(negated, &host) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_match(&pattern, &host),
+        }
+    }
+    matched
+}
+
+/// Match `text` against a pattern where `*` matches any run of characters and
+/// `?` exactly one.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Turn an `Include` argument into the files it names: `~` is expanded,
+/// relative paths are taken from `ssh_dir`, and wildcards in the file name
+/// are matched against the directory contents (sorted, as ssh does).
+fn expand_include(arg: &str, ssh_dir: &Path) -> Vec<PathBuf> {
+    let path = match arg.strip_prefix("~/") {
+        Some(rest) => match dirs::home_dir() {
+            Some(home) => home.join(rest),
+            None => return Vec::new(),
+        },
+        None if Path::new(arg).is_absolute() => PathBuf::from(arg),
+        None => ssh_dir.join(arg),
+    };
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return Vec::new();
+    };
+    if !name.contains(['*', '?']) {
+        return vec![path];
+    }
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| wildcard_match(name, &e.file_name().to_string_lossy()))
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{host_matches, wildcard_match, SshConfig};
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    fn parse(text: &str) -> SshConfig {
+        let mut config = SshConfig::default();
+        config.parse(
+            text,
+            Path::new("/nonexistent"),
+            Some(vec!["*".to_string()]),
+            0,
+        );
+        config
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "github-work"));
+        assert!(wildcard_match("github-*", "github-work"));
+        assert!(wildcard_match("gh?", "gh1"));
+        assert!(wildcard_match("*.corp.example", "git.corp.example"));
+        assert!(!wildcard_match("github-*", "gitlab-work"));
+        assert!(!wildcard_match("gh?", "gh"));
+    }
+
+    #[test]
+    fn test_host_matches_negation() {
+        let patterns = vec!["*-work".to_string(), "!legacy-work".to_string()];
+        assert!(host_matches("github-work", &patterns));
+        assert!(!host_matches("legacy-work", &patterns));
+        assert!(!host_matches("github", &patterns));
+    }
+
+    #[test]
+    fn test_hostname() {
+        let config = parse(
+            "# work account\n\
+             Host github-work\n\
+             \tHostName github.com\n\
+             \tIdentityFile ~/.ssh/id_work\n\
+             \n\
+             Host gl-* !gl-old\n\
+             \x20 HostName=gitlab.com\n\
+             Host *.internal\n\
+             \x20 HostName %h.corp.example\n\
+             Host github.com\n\
+             \x20 User git\n",
+        );
+        assert_eq!(
+            config.hostname("github-work").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            config.hostname("GitHub-Work").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(config.hostname("gl-team").as_deref(), Some("gitlab.com"));
+        assert_eq!(config.hostname("gl-old"), None);
+        assert_eq!(
+            config.hostname("git.internal").as_deref(),
+            Some("git.internal.corp.example")
+        );
+        assert_eq!(config.hostname("github.com"), None);
+        assert_eq!(config.hostname("unknown"), None);
+    }
+
+    #[test]
+    fn test_first_value_wins_and_match_is_ignored() {
+        let config = parse(
+            "Host work\n  HostName first.example\n\
+             Host *\n  HostName fallback.example\n\
+             Match exec \"true\"\n  HostName never.example\n",
+        );
+        assert_eq!(config.hostname("work").as_deref(), Some("first.example"));
+        assert_eq!(
+            config.hostname("other").as_deref(),
+            Some("fallback.example")
+        );
+    }
+
+    #[test]
+    fn test_include() {
+        let mut dir = env::temp_dir();
+        dir.push("git_mirror_ssh_config_include");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("config.d")).expect("create dir");
+        fs::write(
+            dir.join("config"),
+            "Include config.d/*.conf\nHost *\n  User git\n",
+        )
+        .expect("write config");
+        fs::write(
+            dir.join("config.d").join("10-work.conf"),
+            "Host github-work\n  HostName github.com\n",
+        )
+        .expect("write include");
+        fs::write(
+            dir.join("config.d").join("20-lab.conf"),
+            "Host lab\n  HostName gitlab.example.org\n",
+        )
+        .expect("write include");
+        fs::write(
+            dir.join("config.d").join("ignored.txt"),
+            "Host x\n HostName y\n",
+        )
+        .expect("write other");
+
+        let config = SshConfig::load_from(&dir.join("config"), &dir);
+        assert_eq!(
+            config.hostname("github-work").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            config.hostname("lab").as_deref(),
+            Some("gitlab.example.org")
+        );
+        assert_eq!(config.hostname("x"), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_include_inside_host_block() {
+        let mut dir = env::temp_dir();
+        dir.push("git_mirror_ssh_config_include_block");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create dir");
+        fs::write(dir.join("config"), "Host work\n  Include work.conf\n").expect("write config");
+        // Lines before the first Host in an included file inherit the block
+        fs::write(dir.join("work.conf"), "HostName github.com\n").expect("write include");
+
+        let config = SshConfig::load_from(&dir.join("config"), &dir);
+        assert_eq!(config.hostname("work").as_deref(), Some("github.com"));
+        assert_eq!(config.hostname("other"), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
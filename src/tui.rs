@@ -0,0 +1,551 @@
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout as Split};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::forge::WebTarget;
+use crate::fs::{find_repos, remove_repo, Layout};
+use crate::git::{self, RepoStatus, UpdateMode};
+use crate::mirror::Mirror;
+use crate::spec::RepoSpec;
+
+const HELP: &str =
+    "j/k move  / filter  f fetch  p pull  o open  b browse  x remove  r refresh  q quit";
+
+/// One repository in the list. `status` is filled in as it is computed.
+struct Entry {
+    path: PathBuf,
+    name: String,
+    status: Option<Result<RepoStatus, String>>,
+}
+
+/// A fetch or pull finished in the background: the repository, the message
+/// to show and its status afterwards.
+type Done = (PathBuf, String, Result<RepoStatus, String>);
+
+/// Dashboard state, kept apart from the terminal so it can be tested.
+struct App {
+    root: PathBuf,
+    mirror: Mirror,
+    dry_run: bool,
+    entries: Vec<Entry>,
+    filter: String,
+    filtering: bool,
+    /// Position of the selection among the visible entries
+    selected: usize,
+    message: String,
+    /// Repository waiting for a `y` to be removed
+    confirm_remove: Option<PathBuf>,
+    quit: bool,
+    done_tx: Sender<Done>,
+    done: Receiver<Done>,
+}
+
+/// Run the dashboard for every repository under `root` until the user quits.
+pub fn run(root: &Path, layout: Layout, dry_run: bool) -> Result<()> {
+    let mut app = App::new(root, layout, dry_run, find_repos(root));
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn new(root: &Path, layout: Layout, dry_run: bool, repos: Vec<PathBuf>) -> App {
+        let entries = repos
+            .into_iter()
+            .map(|path| Entry {
+                name: path
+                    .strip_prefix(root)
+                    .unwrap_or(&path)
+                    .display()
+                    .to_string(),
+                path,
+                status: None,
+            })
+            .collect();
+        let (done_tx, done) = mpsc::channel();
+        App {
+            root: root.to_path_buf(),
+            // A credential or passphrase prompt would hang behind the raw-mode screen
+            mirror: Mirror::new(root.to_string_lossy(), layout)
+                .dry_run(dry_run)
+                .prompt(false),
+            dry_run,
+            entries,
+            filter: String::new(),
+            filtering: false,
+            selected: 0,
+            message: String::new(),
+            confirm_remove: None,
+            quit: false,
+            done_tx,
+            done,
+        }
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let mut statuses = self.load_statuses();
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(Duration::from_millis(100))? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        if let Some(action) = self.handle_key(key) {
+                            self.message = format!("{}...", action.verb());
+                            terminal.draw(|frame| self.draw(frame))?;
+                            let refresh = action == Action::Refresh;
+                            self.perform(action);
+                            if refresh {
+                                statuses = self.load_statuses();
+                            }
+                            // Editors and browsers may have written to the terminal
+                            terminal.clear()?;
+                        }
+                    }
+                }
+            }
+            for (path, status) in statuses.try_iter() {
+                // Matched by path: removals shift the list while statuses load
+                if let Some(entry) = self.entries.iter_mut().find(|e| e.path == path) {
+                    entry.status = Some(status);
+                }
+            }
+            for (path, message, status) in self.done.try_iter() {
+                self.message = message;
+                if let Some(entry) = self.entries.iter_mut().find(|e| e.path == path) {
+                    entry.status = Some(status);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compute every repository's status on a background thread.
+    fn load_statuses(&mut self) -> Receiver<(PathBuf, Result<RepoStatus, String>)> {
+        let (tx, rx) = mpsc::channel();
+        let paths: Vec<PathBuf> = self.entries.iter().map(|e| e.path.clone()).collect();
+        let mirror = self.mirror.clone();
+        for entry in &mut self.entries {
+            entry.status = None;
+        }
+        thread::spawn(move || {
+            for path in paths {
+                let status = mirror
+                    .status(&path.to_string_lossy())
+                    .map_err(|e| e.to_string());
+                if tx.send((path, status)).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
+    /// Indices of the entries matching the filter.
+    fn visible(&self) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.name.to_lowercase().contains(&filter))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.visible().get(self.selected).copied()
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let count = self.visible().len();
+        if count == 0 {
+            self.selected = 0;
+            return;
+        }
+        self.selected = self.selected.saturating_add_signed(delta).min(count - 1);
+    }
+
+    /// Update the state for a key press; returns the operation to run on the
+    /// selected repository, if the key asked for one.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return None;
+        }
+        if let Some(path) = self.confirm_remove.take() {
+            if key.code == KeyCode::Char('y') {
+                return Some(Action::Remove(path));
+            }
+            self.message = "Nothing removed".to_string();
+            return None;
+        }
+        if self.filtering {
+            match key.code {
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.filtering = false;
+                }
+                KeyCode::Enter => self.filtering = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            self.selected = 0;
+            return None;
+        }
+
+        let path = self.selected_index().map(|i| self.entries[i].path.clone());
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('j') | KeyCode::Down => self.move_selection(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(10),
+            KeyCode::PageUp => self.move_selection(-10),
+            KeyCode::Char('/') => self.filtering = true,
+            KeyCode::Char('r') => return Some(Action::Refresh),
+            KeyCode::Char('f') => return path.map(Action::Fetch),
+            KeyCode::Char('p') => return path.map(Action::Pull),
+            KeyCode::Char('o') => return path.map(Action::Open),
+            KeyCode::Char('b') => return path.map(Action::Browse),
+            KeyCode::Char('x') | KeyCode::Delete => {
+                if let Some(path) = path {
+                    self.ask_remove(path);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Ask for confirmation, unless the repository holds work that would be lost.
+    fn ask_remove(&mut self, path: PathBuf) {
+        match git::unsaved_work(&path) {
+            Ok(work) if work.is_empty() => {
+                self.message = format!("Remove {}? (y/n)", path.display());
+                self.confirm_remove = Some(path);
+            }
+            Ok(work) => {
+                let work: Vec<String> = work.iter().map(|w| w.to_string()).collect();
+                self.message = format!(
+                    "Not removing: {}; use `git-mirror remove --force`",
+                    work.join(", ")
+                );
+            }
+            Err(e) => self.message = format!("Cannot inspect {}: {}", path.display(), e),
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        let mut out: Vec<u8> = Vec::new();
+        let result = match &action {
+            Action::Refresh => Ok("Refreshed".to_string()),
+            Action::Fetch(path) | Action::Pull(path) => {
+                self.spawn_update(path.clone(), matches!(action, Action::Pull(_)));
+                return;
+            }
+            Action::Open(path) => git::open_in_vscode_with_writer(path, self.dry_run, &mut out)
+                .map(|_| last_line(&out).unwrap_or_default()),
+            Action::Browse(path) => self.browse(path, &mut out),
+            Action::Remove(path) => remove_repo(&self.root, path, self.dry_run).map(|removed| {
+                if !self.dry_run {
+                    self.entries.retain(|e| &e.path != path);
+                    self.move_selection(0);
+                }
+                format!("Removed {} director(y/ies)", removed.len())
+            }),
+        };
+        self.message = match result {
+            Ok(message) => message,
+            Err(e) => format!("Error: {:#}", e),
+        };
+    }
+
+    /// Fetch the repository, then fast-forward its branch if `pull`, on a
+    /// background thread; the event loop picks up the result.
+    fn spawn_update(&self, path: PathBuf, pull: bool) {
+        let mirror = self.mirror.clone();
+        let dry_run = self.dry_run;
+        let tx = self.done_tx.clone();
+        thread::spawn(move || {
+            let mut out: Vec<u8> = Vec::new();
+            let repo = path.to_string_lossy().into_owned();
+            let result = mirror
+                .fetch_with_writer(&repo, &mut out)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| {
+                    if !pull {
+                        return Ok(last_line(&out).unwrap_or_else(|| "Fetched".to_string()));
+                    }
+                    git::update_current_branch_with_writer(
+                        &path,
+                        UpdateMode::Pull,
+                        dry_run,
+                        &mut out,
+                    )
+                    .map(|outcome| outcome.to_string())
+                });
+            let message = match result {
+                Ok(message) => message,
+                Err(e) => format!("Error: {:#}", e),
+            };
+            let status = mirror.status(&repo).map_err(|e| e.to_string());
+            let _ = tx.send((path, message, status));
+        });
+    }
+
+    fn browse(&self, path: &Path, out: &mut Vec<u8>) -> Result<String> {
+        let origin = git::origin_url(path)?
+            .ok_or_else(|| anyhow::anyhow!("{} has no origin remote", path.display()))?;
+        let spec = RepoSpec::parse(&origin)?;
+        let layout = self.mirror.layout();
+        let url = layout
+            .forges
+            .web_url(&spec, &layout.ssh, WebTarget::Repo)
+            .ok_or_else(|| anyhow::anyhow!("no web page is known for {}", spec))?;
+        git::open_in_browser_with_writer(&url, self.dry_run, out)?;
+        Ok(last_line(out).unwrap_or(url))
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [list_area, filter_area, status_area] = Split::vertical([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let visible = self.visible();
+        let rows: Vec<Row> = visible
+            .iter()
+            .map(|&i| entry_row(&self.entries[i]))
+            .collect();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(3),
+                Constraint::Fill(1),
+                Constraint::Length(7),
+                Constraint::Length(9),
+                Constraint::Length(10),
+            ],
+        )
+        .header(
+            Row::new(["Repository", "Branch", "Changes", "Upstream", "Fetched"])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::new().borders(Borders::ALL).title(format!(
+            " {} ({}/{}) ",
+            self.root.display(),
+            visible.len(),
+            self.entries.len()
+        )))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, list_area, &mut state);
+
+        let filter = if self.filtering || !self.filter.is_empty() {
+            format!("/{}", self.filter)
+        } else {
+            HELP.to_string()
+        };
+        frame.render_widget(
+            Paragraph::new(filter).style(Style::new().fg(Color::DarkGray)),
+            filter_area,
+        );
+        frame.render_widget(
+            Paragraph::new(Line::from(self.message.as_str())),
+            status_area,
+        );
+    }
+}
+
+/// An operation on the selected repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Refresh,
+    Fetch(PathBuf),
+    Pull(PathBuf),
+    Open(PathBuf),
+    Browse(PathBuf),
+    Remove(PathBuf),
+}
+
+impl Action {
+    fn verb(&self) -> &'static str {
+        match self {
+            Action::Refresh => "Refreshing",
+            Action::Fetch(_) => "Fetching",
+            Action::Pull(_) => "Pulling",
+            Action::Open(_) => "Opening",
+            Action::Browse(_) => "Opening browser",
+            Action::Remove(_) => "Removing",
+        }
+    }
+}
+
+fn entry_row(entry: &Entry) -> Row<'_> {
+    let name = Cell::from(entry.name.as_str());
+    match &entry.status {
+        None => Row::new([name, Cell::from("...")]),
+        Some(Err(e)) => Row::new([name, Cell::from(e.as_str())]).style(Style::new().fg(Color::Red)),
+        Some(Ok(status)) => {
+            let upstream = match status.ahead_behind {
+                Some((0, 0)) => "=".to_string(),
+                Some((ahead, behind)) => format!("+{} -{}", ahead, behind),
+                None => String::new(),
+            };
+            let fetched = status
+                .last_fetch
+                .map(|t| ago(t, SystemTime::now()))
+                .unwrap_or_else(|| "never".to_string());
+            Row::new([
+                name,
+                Cell::from(status.branch.clone().unwrap_or_else(|| "-".to_string())),
+                Cell::from(if status.dirty { "dirty" } else { "" })
+                    .style(Style::new().fg(Color::Yellow)),
+                Cell::from(upstream),
+                Cell::from(fetched),
+            ])
+        }
+    }
+}
+
+/// How long before `now` `time` was, in the largest whole unit.
+fn ago(time: SystemTime, now: SystemTime) -> String {
+    let secs = now.duration_since(time).unwrap_or_default().as_secs();
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+fn last_line(out: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(out)
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ago, Action, App};
+    use crate::fs::Layout;
+    use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::time::{Duration, SystemTime};
+
+    fn app() -> App {
+        let root = Path::new("/w");
+        let repos = ["github/octo/hello", "github/octo/world", "gitlab/g/app"]
+            .iter()
+            .map(|r| root.join(r))
+            .collect();
+        App::new(root, Layout::default(), true, repos)
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Option<Action> {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn test_navigation_and_filter() {
+        let mut app = app();
+        assert_eq!(app.entries[1].name, "github/octo/world");
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected_index(), Some(2));
+        press(&mut app, KeyCode::Char('k'));
+        assert_eq!(
+            press(&mut app, KeyCode::Char('f')),
+            Some(Action::Fetch(PathBuf::from("/w/github/octo/world")))
+        );
+
+        for code in [KeyCode::Char('/'), KeyCode::Char('O'), KeyCode::Char('c')] {
+            press(&mut app, code);
+        }
+        assert_eq!(app.visible(), vec![0, 1]);
+        assert_eq!(app.selected_index(), Some(0));
+        // Keys edit the filter while it is open, then act on the list again
+        assert_eq!(press(&mut app, KeyCode::Char('p')), None);
+        assert!(app.visible().is_empty());
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(
+            press(&mut app, KeyCode::Char('p')),
+            Some(Action::Pull(PathBuf::from("/w/github/octo/hello")))
+        );
+        press(&mut app, KeyCode::Char('/'));
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.visible().len(), 3);
+
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_remove_needs_confirmation() {
+        let mut app = app();
+        app.confirm_remove = Some(PathBuf::from("/w/gitlab/g/app"));
+        assert_eq!(press(&mut app, KeyCode::Char('n')), None);
+        assert_eq!(app.confirm_remove, None);
+        app.confirm_remove = Some(PathBuf::from("/w/gitlab/g/app"));
+        assert_eq!(
+            press(&mut app, KeyCode::Char('y')),
+            Some(Action::Remove(PathBuf::from("/w/gitlab/g/app")))
+        );
+    }
+
+    #[test]
+    fn test_fetch_runs_in_background() {
+        let mut root = env::temp_dir();
+        root.push("git_mirror_tui_fetch");
+        let _ = fs::remove_dir_all(&root);
+        let repo = root.join("github/octo/hello");
+        fs::create_dir_all(&repo).expect("create repo");
+        let status = Command::new("git")
+            .args(["init", "-q"])
+            .arg(&repo)
+            .status()
+            .expect("run git");
+        assert!(status.success());
+
+        let mut app = App::new(&root, Layout::default(), true, vec![repo.clone()]);
+        app.perform(Action::Fetch(repo.clone()));
+        let (path, message, status) = app
+            .done
+            .recv_timeout(Duration::from_secs(10))
+            .expect("fetch finished");
+        assert_eq!(path, repo);
+        assert!(message.starts_with("Dry run: git -C"), "{}", message);
+        assert!(status.is_ok());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_ago() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let before = |secs| now - Duration::from_secs(secs);
+        assert_eq!(ago(before(5), now), "just now");
+        assert_eq!(ago(before(120), now), "2m ago");
+        assert_eq!(ago(before(7200), now), "2h ago");
+        assert_eq!(ago(before(3 * 86_400), now), "3d ago");
+        assert_eq!(ago(now + Duration::from_secs(10), now), "just now");
+    }
+}